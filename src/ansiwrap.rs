@@ -5,8 +5,17 @@ use std::os::unix::io::{RawFd, FromRawFd};
 use std::sync::mpsc::{channel, Sender, Receiver};
 use vte::Parser;
 
+use crate::apc::{ApcFilter, Chunk};
 use crate::client::{Client, ReadHalf};
+use crate::copymode::CopyMode;
+use crate::export;
+use crate::hints::{self, Hints, Pattern};
+use crate::keybind::{self, Action};
+use crate::keyenc::{self, Mods};
+use crate::mode;
+use crate::mouse::{self, MouseSelect};
 use crate::msg::ServerMsg;
+use crate::search::Search;
 use crate::vte_actor::VteActor;
 use skey::{Skey, Sktype};
 use skey::modtrack::ModifierTracker;
@@ -83,12 +92,12 @@ fn cmd_thread(fd: RawFd, tx: Sender<Msg>) {
 	tx.send(Msg::Exit).unwrap();
 }
 
-fn size_conv(size: [i16; 2]) -> Winsize {
+fn size_conv(size: [i16; 2], cell_px: [i16; 2]) -> Winsize {
 	Winsize {
 		ws_row: size[1] as u16,
 		ws_col: size[0] as u16,
-		ws_xpixel: 0,
-		ws_ypixel: 0,
+		ws_xpixel: (size[0] as i32 * cell_px[0] as i32) as u16,
+		ws_ypixel: (size[1] as i32 * cell_px[1] as i32) as u16,
 	}
 }
 
@@ -98,15 +107,27 @@ pub struct VteMaster {
 	rh: Option<ReadHalf>,
 	master: RawFd,
 	parser: Parser,
+	apc: ApcFilter,
 	modtrack: ModifierTracker,
 	debugger: Option<Debugger>,
 	alt_on: bool,
+	// these take the keyboard while active
+	search: Option<Search>,
+	copy_mode: Option<CopyMode>,
+	hints: Option<Hints>,
+	hint_patterns: Vec<Pattern>,
+	select: MouseSelect,
+	// VKOT_SNAP_ON_KEY: leave the scrolled back view when typing
+	snap_on_key: bool,
+	// a status message shown until the next key
+	notice: bool,
 }
 
 impl VteMaster {
-	fn resize(&mut self, tsize: [i16; 2]) {
+	fn resize(&mut self, tsize: [i16; 2], cell_px: [i16; 2]) {
 		self.va.wh.resize(tsize);
-		self.ws = size_conv(tsize);
+		self.va.wh.images.cell_px = cell_px;
+		self.ws = size_conv(tsize, cell_px);
 		unsafe {tiocswinsz(self.master, &self.ws).unwrap(); }
 	}
 
@@ -114,8 +135,8 @@ impl VteMaster {
 		let (mut rh, mut wh) = Client::default().unwrap();
 		let event = rh.poll_event().unwrap();
 		let parser = vte::Parser::new();
-		let tsize = if let ServerMsg::Resized(tsize) = event {
-			tsize
+		let (tsize, cell_px) = if let ServerMsg::Resized(tsize, cell_px) = event {
+			(tsize, cell_px)
 		} else {
 			panic!("First msg not size!");
 		};
@@ -129,47 +150,212 @@ impl VteMaster {
 		};
 
 		let mut result = Self {
-			ws: size_conv([0, 0]), // useless
+			ws: size_conv([0, 0], [0, 0]), // useless
 			va,
 			rh: Some(rh),
 			master,
 			parser,
+			apc: Default::default(),
 			debugger,
 			modtrack: Default::default(),
 			alt_on: false,
+			search: None,
+			copy_mode: None,
+			hints: None,
+			hint_patterns: hints::patterns(),
+			select: Default::default(),
+			snap_on_key: std::env::var("VKOT_SNAP_ON_KEY").is_ok(),
+			notice: false,
 		};
-		result.resize(tsize);
+		result.resize(tsize, cell_px);
 		result
 	}
 
+	fn mods(&self) -> Mods {
+		Mods {
+			shift: self.modtrack.shift,
+			alt: self.alt_on,
+			ctrl: self.modtrack.ctrl,
+		}
+	}
+
+	// scrolling keys go to the application without history to scroll
+	fn usable(&self, action: &Action) -> bool {
+		let wh = &self.va.wh;
+		!action.scrolls() || (wh.first_line() < wh.abs_line(0) && !self.va.alt_screen())
+	}
+
+	fn run_action(&mut self, action: Action) {
+		let wh = &mut self.va.wh;
+		match action {
+			Action::ScrollLines(lines) => wh.scroll_history(lines),
+			Action::ScrollPage(up) => wh.scroll_history_page(!up),
+			Action::ScrollTop => wh.scroll_history_top(),
+			Action::ScrollBottom => wh.snap_bottom(),
+			Action::Search => self.search = Some(Search::new(wh)),
+			Action::CopyMode => self.copy_mode = Some(CopyMode::new(wh)),
+			Action::Hints => self.hints = Hints::new(wh, &self.hint_patterns),
+			Action::Export(format) => {
+				let status = match export::export_file(wh, format) {
+					Ok(path) => format!("exported to {}", path.display()),
+					Err(e) => format!("export failed: {}", e),
+				};
+				wh.set_status(Some(&status));
+				self.notice = true;
+			}
+		}
+	}
+
+	fn clear_notice(&mut self) {
+		if self.notice {
+			self.notice = false;
+			self.va.wh.set_status(None);
+		}
+	}
+
+	fn captured(&self) -> bool {
+		self.search.is_some() || self.copy_mode.is_some() || self.hints.is_some()
+	}
+
+	fn send_skey(&mut self, file: &mut File, skey: &Skey) {
+		let bytes = keyenc::encode_skey(
+			skey,
+			self.mods(),
+			self.va.modes.get(true, mode::DECCKM),
+			self.va.kitty_flags(),
+		);
+		if let Some(bytes) = bytes {
+			self.send_key(file, &bytes);
+		}
+	}
+
+	fn send_key(&mut self, file: &mut File, bytes: &[u8]) {
+		if self.snap_on_key {
+			self.va.wh.snap_bottom();
+		}
+		file.write_all(bytes).unwrap();
+	}
+
+	// return false if suppressed by a synchronized update
+	fn flush(&mut self) -> bool {
+		// the application may never end the update
+		const SYNC_TIMEOUT: u64 = 150;
+		if let Some(since) = self.va.sync_since {
+			let dur = since.elapsed()
+				.map(|x| x.as_millis() as u64)
+				.unwrap_or(0);
+			if dur < SYNC_TIMEOUT {
+				return false
+			}
+			self.va.end_sync();
+		}
+		self.va.wh.send_damage().unwrap();
+		true
+	}
+
 	// return true = exit
 	fn proc_msg(&mut self, file: &mut File, msg: Msg) -> bool {
 		match msg {
 			Msg::CmdRead(bytes) => {
-				for byte in bytes.into_iter() {
-					// if byte > 0 {eprint!("{:?}", byte as char);}
-					self.parser.advance(&mut self.va, byte);
-					if let Some(debugger) = self.debugger.as_mut() {
-						debugger.p(byte);
-						std::thread::sleep(std::time::Duration::from_millis(debugger.sleep));
-						self.va.wh.send_damage().unwrap();
+				for chunk in self.apc.filter(bytes).into_iter() {
+					let bytes = match chunk {
+						Chunk::Vt(bytes) => bytes,
+						Chunk::Apc(data) => {
+							self.va.apc_dispatch(data);
+							continue
+						}
+						Chunk::Iterm(data) => {
+							self.va.iterm_dispatch(data);
+							continue
+						}
+					};
+					for byte in bytes.into_iter() {
+						// if byte > 0 {eprint!("{:?}", byte as char);}
+						self.parser.advance(&mut self.va, byte);
+						if let Some(debugger) = self.debugger.as_mut() {
+							debugger.p(byte);
+							std::thread::sleep(std::time::Duration::from_millis(debugger.sleep));
+							self.va.wh.send_damage().unwrap();
+						}
 					}
 				}
+				if !self.va.reply.is_empty() {
+					file.write_all(&self.va.reply).unwrap();
+					self.va.reply.clear();
+				}
 			}
 			Msg::Vtc(vtc) => {
 				match vtc {
 					ServerMsg::Getch(ch) => {
-						if ch < 127 {
-							if self.alt_on {
-								file.write(b"\x1b").unwrap();
-								self.alt_on = false;
+						self.clear_notice();
+						if let Some(search) = self.search.as_mut() {
+							if !search.key(&mut self.va.wh, ch) {
+								self.search = None;
+							}
+							return false
+						}
+						if let Some(copy_mode) = self.copy_mode.as_mut() {
+							if !copy_mode.key(&mut self.va.wh, ch, self.alt_on) {
+								self.copy_mode = None;
+							}
+							self.alt_on = false;
+							return false
+						}
+						if let Some(hints) = self.hints.as_mut() {
+							if !hints.key(&mut self.va.wh, ch) {
+								self.hints = None;
 							}
-							file.write(&[ch as u8]).unwrap();
+							return false
+						}
+						if let Some(action) = keybind::char_action(ch, self.mods()) {
+							self.run_action(action);
+							return false
 						}
+						let kitty = self.va.kitty_flags();
+						let bytes = match keyenc::encode_char_kitty(ch, self.mods(), kitty) {
+							Some(bytes) => bytes,
+							None => keyenc::encode_char(
+								ch,
+								self.alt_on,
+								self.va.modes.get(true, mode::META_ESC),
+								self.va.modes.get(true, mode::DECBKM),
+							),
+						};
+						self.alt_on = false;
+						self.send_key(file, &bytes);
 					}
-					ServerMsg::Resized(new_size) => {
-						self.resize(new_size);
+					ServerMsg::Resized(new_size, cell_px) => {
+						self.resize(new_size, cell_px);
 					},
+					ServerMsg::Focus(focused) => {
+						if self.va.modes.get(true, mode::FOCUS) {
+							let seq = if focused { b"\x1b[I" } else { b"\x1b[O" };
+							file.write_all(seq).unwrap();
+						}
+					}
+					ServerMsg::Mouse(button, kind, pos) => {
+						// shift selects even if the application tracks the mouse
+						if !self.modtrack.shift && mouse::tracking(&self.va.modes) {
+							let bytes = mouse::encode(
+								&self.va.modes,
+								button,
+								kind,
+								pos,
+								self.mods(),
+							);
+							if let Some(bytes) = bytes {
+								file.write_all(&bytes).unwrap();
+							}
+							return false
+						}
+						if self.captured() {
+							return false
+						}
+						match keybind::mouse_action(button, self.mods()).filter(|x| self.usable(x)) {
+							Some(action) if kind == 0 => self.run_action(action),
+							_ => self.select.event(&mut self.va.wh, button, kind, pos),
+						}
+					}
 					ServerMsg::Skey(bytes) => {
 						let skey = if let Some(skey) = Skey::des(bytes) {
 							skey
@@ -179,33 +365,23 @@ impl VteMaster {
 						if !skey.down {
 							match skey.ty {
 								Sktype::Modifier(3) => self.alt_on = false,
-								_ => {},
+								Sktype::Modifier(_) => self.modtrack.update_skey(skey),
+								_ if self.captured() => {}
+								_ => self.send_skey(file, &skey),
 							}
 							return false
 						}
+						if !matches!(skey.ty, Sktype::Modifier(_)) {
+							self.clear_notice();
+						}
 						match skey.ty {
-							Sktype::Direction(x) => {
-								match x {
-									0 => {
-										file.write(b"\x1b[D").unwrap();
-									}
-									1 => {
-										file.write(b"\x1b[A").unwrap();
-									}
-									2 => {
-										file.write(b"\x1b[C").unwrap();
-									}
-									3 => {
-										file.write(b"\x1b[B").unwrap();
-									}
-									6 | 7 => {
-										if self.modtrack.shift {
-											let down = x == 7;
-											self.va.wh.scroll_history_page(down);
-										}
-									}
-									_ => {},
-								}
+							Sktype::Direction(x @ (1 | 3)) if self.search.is_some() => {
+								let search = self.search.as_mut().unwrap();
+								search.step(&mut self.va.wh, x == 1);
+							}
+							Sktype::Direction(_) if self.copy_mode.is_some() => {
+								let copy_mode = self.copy_mode.as_mut().unwrap();
+								copy_mode.skey(&mut self.va.wh, &skey.ty);
 							}
 							Sktype::Modifier(x) => {
 								if x == 3 {
@@ -214,7 +390,13 @@ impl VteMaster {
 									self.modtrack.update_skey(skey);
 								}
 							}
-							_ => {},
+							_ if self.captured() => {}
+							_ => match keybind::skey_action(&skey.ty, self.mods())
+								.filter(|x| self.usable(x))
+							{
+								Some(action) => self.run_action(action),
+								None => self.send_skey(file, &skey),
+							}
 						}
 					},
 				}
@@ -231,7 +413,7 @@ impl VteMaster {
 			if self.proc_msg(&mut file, msg) {
 				return
 			}
-			self.va.wh.send_damage().unwrap();
+			self.flush();
 		}
 	}
 
@@ -270,7 +452,7 @@ impl VteMaster {
 							.as_millis() as u64;
 						if dur > FTIME {
 							prev_send = new_time;
-							self.va.wh.send_damage().unwrap();
+							self.flush();
 						}
 					} else {
 						send_counter += 1;
@@ -284,8 +466,11 @@ impl VteMaster {
 						.as_millis() as u64;
 					if dur > FTIME {
 						prev_send = new_time;
-						self.va.wh.send_damage().unwrap();
-						tryr = RecvType::Block;
+						tryr = if self.flush() {
+							RecvType::Block
+						} else {
+							RecvType::Timeout(FTIME)
+						};
 					} else {
 						tryr = RecvType::Timeout(FTIME - dur + 1);
 					}
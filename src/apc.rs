@@ -0,0 +1,194 @@
+// vte ignores APC strings and keeps only 1024 bytes of an OSC string,
+// so APC and OSC 1337 are split out before the parser
+
+// drop strings longer than this
+const MAX_LEN: usize = 64 << 20;
+
+const ITERM_PREFIX: &[u8] = b"1337;";
+
+enum State {
+	Ground,
+	Esc,
+	Apc,
+	ApcEsc,
+	// start of an OSC string, kept in buf until it matches ITERM_PREFIX or not
+	Osc,
+	Iterm,
+	ItermEsc,
+}
+
+pub enum Chunk {
+	Vt(Vec<u8>),
+	Apc(Vec<u8>),
+	// OSC 1337 without the prefix
+	Iterm(Vec<u8>),
+}
+
+pub struct ApcFilter {
+	state: State,
+	buf: Vec<u8>,
+}
+
+impl Default for ApcFilter {
+	fn default() -> Self {
+		Self {
+			state: State::Ground,
+			buf: Vec::new(),
+		}
+	}
+}
+
+impl ApcFilter {
+	pub fn filter(&mut self, bytes: Vec<u8>) -> Vec<Chunk> {
+		let mut result = Vec::new();
+		let mut vt = Vec::new();
+		for byte in bytes.into_iter() {
+			match self.state {
+				State::Ground => {
+					if byte == 0x1b {
+						self.state = State::Esc;
+					} else {
+						vt.push(byte);
+					}
+				}
+				State::Esc => {
+					if (byte == b'_' || byte == b']') && !vt.is_empty() {
+						result.push(Chunk::Vt(std::mem::take(&mut vt)));
+					}
+					self.filter_esc(byte, &mut vt);
+				}
+				State::Apc => {
+					if byte == 0x1b {
+						self.state = State::ApcEsc;
+					} else if self.buf.len() < MAX_LEN {
+						self.buf.push(byte);
+					}
+				}
+				State::ApcEsc => {
+					if byte == b'\\' {
+						self.state = State::Ground;
+						result.extend(self.take_string(Chunk::Apc));
+					} else {
+						// ESC aborts the string and starts a new sequence
+						self.buf.clear();
+						self.filter_esc(byte, &mut vt);
+					}
+				}
+				State::Osc => {
+					if ITERM_PREFIX.get(self.buf.len()) != Some(&byte) {
+						// other OSC strings are left to vte
+						vt.extend(b"\x1b]");
+						vt.append(&mut self.buf);
+						if byte == 0x1b {
+							self.state = State::Esc;
+						} else {
+							self.state = State::Ground;
+							vt.push(byte);
+						}
+					} else if self.buf.len() + 1 == ITERM_PREFIX.len() {
+						self.buf.clear();
+						self.state = State::Iterm;
+					} else {
+						self.buf.push(byte);
+					}
+				}
+				State::Iterm => {
+					if byte == 0x07 {
+						self.state = State::Ground;
+						result.extend(self.take_string(Chunk::Iterm));
+					} else if byte == 0x1b {
+						self.state = State::ItermEsc;
+					} else if self.buf.len() < MAX_LEN {
+						self.buf.push(byte);
+					}
+				}
+				State::ItermEsc => {
+					if byte == b'\\' {
+						self.state = State::Ground;
+						result.extend(self.take_string(Chunk::Iterm));
+					} else {
+						self.buf.clear();
+						self.filter_esc(byte, &mut vt);
+					}
+				}
+			}
+		}
+		if !vt.is_empty() {
+			result.push(Chunk::Vt(vt));
+		}
+		result
+	}
+
+	// finished string in buf, None if it was cut at MAX_LEN
+	fn take_string(&mut self, chunk: fn(Vec<u8>) -> Chunk) -> Option<Chunk> {
+		if self.buf.len() < MAX_LEN {
+			Some(chunk(std::mem::take(&mut self.buf)))
+		} else {
+			eprintln!("string too long");
+			self.buf.clear();
+			None
+		}
+	}
+
+	// byte following an ESC
+	fn filter_esc(&mut self, byte: u8, vt: &mut Vec<u8>) {
+		match byte {
+			b'_' => self.state = State::Apc,
+			b']' => self.state = State::Osc,
+			0x1b => {
+				self.state = State::Esc;
+				vt.push(0x1b);
+			}
+			_ => {
+				self.state = State::Ground;
+				vt.push(0x1b);
+				vt.push(byte);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn split(filter: &mut ApcFilter, bytes: &[u8]) -> Vec<(char, Vec<u8>)> {
+		filter.filter(bytes.to_vec()).into_iter().map(|chunk| match chunk {
+			Chunk::Vt(x) => ('v', x),
+			Chunk::Apc(x) => ('a', x),
+			Chunk::Iterm(x) => ('i', x),
+		}).collect()
+	}
+
+	#[test]
+	fn iterm_split_out() {
+		let mut filter = ApcFilter::default();
+		let chunks = split(&mut filter, b"a\x1b]1337;File=:QQ==\x07b\x1b]1337;x\x1b\\");
+		assert_eq!(chunks, vec![
+			('v', b"a".to_vec()),
+			('i', b"File=:QQ==".to_vec()),
+			('v', b"b".to_vec()),
+			('i', b"x".to_vec()),
+		]);
+	}
+
+	#[test]
+	fn other_osc_passed_through() {
+		let mut filter = ApcFilter::default();
+		let mut chunks = split(&mut filter, b"\x1b]0;title\x07\x1b]13");
+		chunks.extend(split(&mut filter, b"38;x\x07"));
+		let vt: Vec<u8> = chunks.into_iter().flat_map(|(ty, x)| {
+			assert_eq!(ty, 'v');
+			x
+		}).collect();
+		assert_eq!(vt, b"\x1b]0;title\x07\x1b]1338;x\x07");
+	}
+
+	#[test]
+	fn iterm_split_across_reads() {
+		let mut filter = ApcFilter::default();
+		assert!(split(&mut filter, b"\x1b]13").is_empty());
+		assert!(split(&mut filter, b"37;ab").is_empty());
+		assert_eq!(split(&mut filter, b"c\x07"), vec![('i', b"abc".to_vec())]);
+	}
+}
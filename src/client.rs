@@ -1,11 +1,29 @@
+use std::borrow::Cow;
 use std::collections::VecDeque;
 use std::io::{BufWriter, Read, Write, Result};
 use std::os::unix::net::UnixStream;
 
+use crate::export::{self, Format};
+use crate::image::{Image, ImageStore};
+use crate::line::{self, Line, Packed};
 use crate::msg::ServerMsg;
 use vkot_common::cell::Cell;
 use vkot_common::region::Region;
 
+// decoration bits of Cell::de
+pub const DE_UNDERLINE: u32 = 1 << 2;
+// 1 = single, 2 = double, 3 = curly, 4 = dotted, 5 = dashed
+pub const DE_ULSTYLE_SHIFT: u32 = 8;
+pub const DE_ULSTYLE: u32 = 7 << DE_ULSTYLE_SHIFT;
+// underline color, as a 256 color index since Cell has no room for rgb
+pub const DE_ULCOLOR_SET: u32 = 1 << 11;
+// DECSCA, kept from selective erase
+pub const DE_PROTECTED: u32 = 1 << 12;
+// written with SGR 7 or reversed by DECCARA, fg and bg are already swapped
+pub const DE_REVERSED: u32 = 1 << 13;
+pub const DE_ULCOLOR_SHIFT: u32 = 16;
+pub const DE_ULCOLOR: u32 = 0xFF << DE_ULCOLOR_SHIFT;
+
 pub fn wide_test(ch: char) -> (bool, i16) {
 	let wide = match unicode_width::UnicodeWidthChar::width(ch) {
 		Some(x) => x >= 2,
@@ -18,12 +36,52 @@ pub fn wide_test(ch: char) -> (bool, i16) {
 	}
 }
 
+// how an overlay changes the cells it covers
+#[derive(Clone, Copy)]
+pub enum Mark {
+	// fg, bg
+	Color(u32, u32),
+	Reverse,
+	Replace(Cell),
+}
+
+// drawn over the view without touching stored cells
+pub struct Overlay {
+	// absolute line number, see WriteHalf::abs_line
+	pub line: usize,
+	pub x0: usize,
+	pub x1: usize,
+	pub mark: Mark,
+}
+
+fn status_blank() -> Cell {
+	Cell { fg: 0x000000FF, bg: 0xC0C0C0FF, ..Default::default() }
+}
+
+// VKOT_SCROLLBACK: number of lines, 0 or unlimited
+fn scrollback_limit() -> usize {
+	match std::env::var("VKOT_SCROLLBACK") {
+		Ok(x) if x == "unlimited" => usize::MAX,
+		Ok(x) => x.parse().unwrap_or_else(|_| {
+			eprintln!("bad VKOT_SCROLLBACK {:?}", x);
+			10000
+		}),
+		Err(_) => 10000,
+	}
+}
+
 pub struct WriteHalf {
 	writer: BufWriter<UnixStream>,
-	history: VecDeque<Vec<Cell>>,
+	history: VecDeque<Packed>,
+	// number of lines dropped from history
+	hist_base: usize,
+	// max lines in history
+	scrollback: usize,
 	histcur: usize,
-	buffer: Vec<Vec<Cell>>,
-	// current empty cell
+	// lines scrolled into history while the view is scrolled back
+	new_lines: usize,
+	buffer: Vec<Line>,
+	// current pen
 	ecell: Cell,
 	reversed: bool,
 	// all in x, y(or col, row) order
@@ -31,7 +89,16 @@ pub struct WriteHalf {
 	damage: Region,
 	cursor: [i16; 2],
 	scroll_region: [usize; 2],
+	// DECSLRM, left inclusive and right exclusive
+	margins: [usize; 2],
+	// cursor, pen and reversed
+	saved_cursor: Option<([i16; 2], Cell, bool)>,
 	eol: bool,
+	pub images: ImageStore,
+	// sorted by line
+	overlays: Vec<Overlay>,
+	// replaces the bottom row of the view if not empty
+	status: Vec<Cell>,
 }
 
 impl WriteHalf {
@@ -39,36 +106,333 @@ impl WriteHalf {
 		Self {
 			writer: BufWriter::new(stream),
 			history: VecDeque::new(),
+			hist_base: 0,
+			scrollback: scrollback_limit(),
 			histcur: 0,
-			buffer: vec![vec![Cell::default(); 80]; 24],
+			new_lines: 0,
+			buffer: vec![Line::new(Cell::default(), 80); 24],
 			ecell: Cell::default(),
 			reversed: false,
 			size: [80, 24],
 			scroll_region: [0, 24],
+			margins: [0, 80],
+			saved_cursor: None,
 			damage: Region::default(),
 			cursor: [0; 2],
 			eol: false,
+			images: Default::default(),
+			overlays: Vec::new(),
+			status: Vec::new(),
+		}
+	}
+
+	// line number counted from the first line ever scrolled into history
+	pub fn abs_line(&self, y: i16) -> usize {
+		self.hist_base + self.history.len() + y as usize
+	}
+
+	// absolute line number of the first row on screen, scrolled by histcur
+	pub fn view_top(&self) -> usize {
+		self.abs_line(0) - self.histcur
+	}
+
+	// lines in history and buffer are first_line()..end_line()
+	pub fn first_line(&self) -> usize {
+		self.hist_base
+	}
+
+	pub fn end_line(&self) -> usize {
+		self.abs_line(self.size[1])
+	}
+
+	pub fn get_line(&self, line: usize) -> Option<Cow<'_, Line>> {
+		let idx = line.checked_sub(self.hist_base)?;
+		let hlen = self.history.len();
+		if idx < hlen {
+			Some(Cow::Owned(self.history[hlen - idx - 1].unpack()))
+		} else {
+			self.buffer.get(idx - hlen).map(Cow::Borrowed)
+		}
+	}
+
+	// history and buffer, see export::write_lines
+	pub fn export<W: Write>(&self, writer: &mut W, format: Format) -> Result<()> {
+		let lines = (self.first_line()..self.end_line())
+			.filter_map(|y| self.get_line(y).map(Cow::into_owned));
+		export::write_lines(writer, lines, format)
+	}
+
+	// scroll the view if the line is not on it
+	pub fn show_line(&mut self, line: usize) {
+		let rows = self.size[1] as usize;
+		let view_top = self.view_top();
+		if line >= view_top && line < view_top + rows {
+			return
+		}
+		let screen_top = self.abs_line(0);
+		let target = line.saturating_sub(rows / 2).clamp(self.hist_base, screen_top);
+		self.set_histcur(screen_top - target);
+	}
+
+	fn set_histcur(&mut self, histcur: usize) {
+		self.histcur = histcur.min(self.history.len());
+		if self.histcur == 0 {
+			self.new_lines = 0;
+		}
+		self.damage_all();
+	}
+
+	pub fn snap_bottom(&mut self) {
+		if self.histcur != 0 {
+			self.set_histcur(0);
+		}
+	}
+
+	// right aligned at the bottom of a scrolled back view
+	fn indicator(&self) -> Vec<Cell> {
+		if self.histcur == 0 || self.new_lines == 0 {
+			return Vec::new()
 		}
+		let text = format!(" {} new lines below ", self.new_lines);
+		text.chars().map(|ch| status_blank().with_unic(ch as u32)).collect()
+	}
+
+	pub fn set_overlays(&mut self, mut overlays: Vec<Overlay>) {
+		overlays.sort_by_key(|x| x.line);
+		self.overlays = overlays;
+		self.damage_all();
+	}
+
+	pub fn set_status(&mut self, status: Option<&str>) {
+		let blank = status_blank();
+		self.status = match status {
+			Some(text) => {
+				let mut cells: Vec<Cell> = text.chars()
+					.map(|ch| blank.with_unic(ch as u32))
+					.collect();
+				cells.resize(cells.len().max(self.size[0] as usize), blank);
+				cells
+			}
+			None => Vec::new(),
+		};
+		self.damage_all();
+	}
+
+	fn decorate(
+		&self,
+		mut cell: Cell,
+		line: usize,
+		[x, y]: [usize; 2],
+		indicator: &[Cell],
+	) -> Cell {
+		let cols = self.size[0] as usize;
+		if y + 1 == self.size[1] as usize {
+			if !self.status.is_empty() {
+				return self.status.get(x).cloned().unwrap_or(status_blank())
+			}
+			if x + indicator.len() >= cols {
+				if let Some(cell) = indicator.get(x + indicator.len() - cols) {
+					return *cell
+				}
+			}
+		}
+		let start = self.overlays.partition_point(|o| o.line < line);
+		for overlay in self.overlays[start..].iter() {
+			if overlay.line != line {
+				break
+			}
+			if x < overlay.x0 || x >= overlay.x1 {
+				continue
+			}
+			match overlay.mark {
+				Mark::Color(fg, bg) => {
+					cell.fg = fg;
+					cell.bg = bg;
+				}
+				Mark::Reverse => {
+					let fg = (cell.fg & 0xFF) | (cell.bg & 0xFFFFFF00);
+					[cell.fg, cell.bg] = [fg, cell.fg];
+				}
+				Mark::Replace(new) => cell = new,
+			}
+		}
+		cell
+	}
+
+	// anchor the image at cursor and move the cursor below it
+	pub fn place_image(&mut self, image: Image) {
+		let id = match self.images.add(None, image) {
+			Some(id) => id,
+			None => return,
+		};
+		let line = self.abs_line(self.cursor[1]);
+		let [_, rows] = self.images.place(id, 0, line, self.cursor[0]);
+		for _ in 0..rows {
+			self.newline();
+		}
+		self.loc(0, 0);
+	}
+
+	// kitty moves the cursor right of the image, on its last row
+	pub fn place_kitty(&mut self, id: u32, pid: u32, move_cursor: bool) {
+		let line = self.abs_line(self.cursor[1]);
+		let col = self.cursor[0];
+		let [cols, rows] = self.images.place(id, pid, line, col);
+		if !move_cursor || rows == 0 {
+			return
+		}
+		for _ in 1..rows {
+			self.newline();
+		}
+		self.loc(0, col.saturating_add(cols as i16));
 	}
 
 	pub fn resize(&mut self, new_size: [i16; 2]) {
 		eprintln!("resizing to {:?}", new_size);
-		self.buffer.resize(new_size[1] as usize, vec![self.ecell; new_size[0] as usize]);
+		if new_size[0] != self.size[0] {
+			self.reflow(new_size[0] as usize);
+		}
+		let blank = self.erase_cell();
+		let rows = self.size[1] as usize;
+		let new_rows = new_size[1] as usize;
+		if new_rows < rows {
+			// keep the cursor line, the top goes into history
+			let push = (self.cursor[1] as usize + 1).saturating_sub(new_rows);
+			for line in self.buffer.drain(..push) {
+				self.history.push_front(Packed::pack(&line));
+			}
+			self.trim_history();
+			self.cursor[1] -= push as i16;
+		} else if new_rows > rows {
+			let pull = (new_rows - rows).min(self.history.len());
+			for _ in 0..pull {
+				let line = self.history.pop_front().unwrap();
+				self.buffer.insert(0, line.unpack());
+			}
+			self.cursor[1] += pull as i16;
+		}
+		self.set_histcur(self.histcur);
+		self.buffer.resize(new_rows, Line::new(blank, new_size[0] as usize));
 		for line in self.buffer.iter_mut() {
-			line.resize(new_size[0] as usize, self.ecell);
+			line.resize(new_size[0] as usize, blank);
 		}
 		self.size = new_size;
 		self.scroll_region = [0, new_size[1] as usize];
+		self.reset_margins();
+		self.limit_cursor();
 	}
 
+	// rewrap history and buffer into cols,
+	// the cursor stays on the same screen row
+	fn reflow(&mut self, cols: usize) {
+		let hlen = self.history.len();
+		let mut lines: Vec<Line> = self.history.drain(..)
+			.rev()
+			.map(|x| x.unpack())
+			.collect();
+		lines.append(&mut self.buffer);
+		let cursor = [self.cursor[0] as usize, hlen + self.cursor[1] as usize];
+		let (mut lines, mut cursor) = line::reflow(lines, cols, cursor);
+		let rows = self.size[1] as usize;
+		let top = cursor[1].saturating_sub(self.cursor[1] as usize);
+		lines.truncate(top + rows);
+		self.buffer = lines.split_off(top);
+		self.buffer.resize(rows, Line::new(self.erase_cell(), cols));
+		self.history = lines.iter().rev().map(Packed::pack).collect();
+		self.trim_history();
+		self.set_histcur(0);
+		// line numbers no longer match the old rows
+		self.images.clear_placements();
+		self.images.prune(self.first_line());
+		// a pending wrap is no longer pending if the line got wider
+		let eol = self.eol && cursor[0] + 1 >= cols;
+		if self.eol && !eol {
+			cursor[0] += 1;
+		}
+		self.cursor = [cursor[0] as i16, (cursor[1] - top) as i16];
+		self.eol = eol;
+		self.damage_all();
+	}
+
+	fn trim_history(&mut self) {
+		let hlen = self.history.len();
+		if hlen > self.scrollback {
+			self.history.truncate(self.scrollback);
+			self.hist_base += hlen - self.scrollback;
+		}
+	}
+
+	// top and bot start from 1
 	pub fn set_scroll_region(&mut self, top: u16, bot: u16) {
-		self.scroll_region = [top as usize, bot as usize];
+		let bot = (bot as usize).min(self.size[1] as usize);
+		if top as usize >= bot {
+			return
+		}
+		self.scroll_region = [top as usize - 1, bot];
+	}
+
+	// left and right start from 1
+	pub fn set_margins(&mut self, left: u16, right: u16) {
+		let right = (right as usize).min(self.size[0] as usize);
+		if left as usize >= right {
+			return
+		}
+		self.margins = [left as usize - 1, right];
+		self.loc(0, 0);
+		self.loc(1, 0);
+	}
+
+	pub fn reset_margins(&mut self) {
+		self.margins = [0, self.size[0] as usize];
+	}
+
+	// horizontal bounds for the cursor, margins apply only inside them
+	fn line_bounds(&self) -> [i16; 2] {
+		let [left, right] = self.margins;
+		let cx = self.cursor[0] as usize;
+		if cx >= left && cx < right {
+			[left as i16, right as i16]
+		} else {
+			[0, self.size[0]]
+		}
+	}
+
+	pub fn save_cursor(&mut self) {
+		self.saved_cursor = Some((self.cursor, self.ecell, self.reversed));
+	}
+
+	pub fn restore_cursor(&mut self) {
+		let (cursor, ecell, reversed) = self.saved_cursor
+			.unwrap_or(([0, 0], Cell::default(), false));
+		self.ecell = ecell;
+		self.reversed = reversed;
+		self.loc(0, cursor[0]);
+		self.loc(1, cursor[1]);
+	}
+
+	pub fn size(&self) -> [i16; 2] {
+		self.size
+	}
+
+	pub fn cursor(&self) -> [i16; 2] {
+		self.cursor
+	}
+
+	pub fn scroll_region(&self) -> [usize; 2] {
+		self.scroll_region
+	}
+
+	// current pen and whether it is reversed
+	pub fn pen(&self) -> (Cell, bool) {
+		(self.ecell, self.reversed)
 	}
 
 	pub fn clear(&mut self) {
 		let sx = self.size[0] as usize;
 		let sy = self.size[1] as usize;
-		self.buffer = vec![vec![self.ecell; sx]; sy];
+		self.buffer = vec![Line::new(self.erase_cell(), sx); sy];
+		let first = self.abs_line(0);
+		self.images.erase_lines(first, first + sy);
 	}
 
 	pub fn reset(&mut self) {
@@ -89,8 +453,9 @@ impl WriteHalf {
 		if target >= self.size[0] as usize {
 			return
 		}
+		let blank = self.erase_cell();
 		for i in cx..target {
-			self.buffer[self.cursor[1] as usize][i] = self.ecell;
+			self.buffer[self.cursor[1] as usize][i] = blank;
 		}
 		let target = target as i16;
 		self.loc(0, target);
@@ -119,34 +484,78 @@ impl WriteHalf {
 
 	pub fn scroll(&mut self, down: bool) {
 		self.damage_all();
+		let [top, bot] = self.scroll_region;
+		let [left, right] = self.margins;
+		let top_line = self.abs_line(top as i16);
+		let bot_line = self.abs_line(bot as i16);
+		if left != 0 || right != self.size[0] as usize {
+			// images aren't clipped to the margins, drop them
+			self.images.erase_lines(top_line, bot_line);
+			// only the rectangle moves, nothing goes into history
+			let blank = self.erase_cell();
+			if down {
+				for y in top..bot - 1 {
+					let src = self.buffer[y + 1][left..right].to_vec();
+					self.buffer[y][left..right].copy_from_slice(&src);
+				}
+				self.buffer[bot - 1][left..right].fill(blank);
+			} else {
+				for y in (top + 1..bot).rev() {
+					let src = self.buffer[y - 1][left..right].to_vec();
+					self.buffer[y][left..right].copy_from_slice(&src);
+				}
+				self.buffer[top][left..right].fill(blank);
+			}
+			return
+		}
 		if down {
 			self.buffer.insert(
-				self.scroll_region[1],
-				vec![self.ecell; self.size[0] as usize],
+				bot,
+				Line::new(self.erase_cell(), self.size[0] as usize),
 			);
-			self.history.push_front(self.buffer.remove(self.scroll_region[0]));
-			let hlen = self.history.len();
-			if hlen > 10000 {
-				self.history.drain(10001..);
+			let line = self.buffer.remove(top);
+			if top != 0 {
+				self.images.scroll_lines(top_line, bot_line, -1);
+				return
+			}
+			self.history.push_front(Packed::pack(&line));
+			// the region keeps its line numbers, the rows below it don't
+			self.images.renumber(bot_line, 1);
+			if self.histcur > 0 {
+				// keep the view on the same lines
+				self.histcur += 1;
+				self.new_lines += 1;
 			}
+			self.trim_history();
+			self.histcur = self.histcur.min(self.history.len());
 		} else {
 			self.buffer.insert(
-				self.scroll_region[0] as usize,
-				vec![self.ecell; self.size[0] as usize],
+				top,
+				Line::new(self.erase_cell(), self.size[0] as usize),
 			);
-			self.buffer.pop();
+			self.buffer.remove(bot);
+			self.images.scroll_lines(top_line, bot_line, 1);
 		}
 	}
 
 	pub fn scroll_history_page(&mut self, down: bool) {
-		let dy = self.size[1] as usize / 2;
-		if down {
-			self.histcur = self.histcur.saturating_sub(dy)
-		} else {
-			self.histcur += dy;
-			self.histcur = self.histcur.min(self.history.len());
-		}
-		self.damage_all();
+		let dy = self.size[1] as isize / 2;
+		self.scroll_history(if down { -dy } else { dy });
+	}
+
+	// positive = towards older lines
+	pub fn scroll_history(&mut self, lines: isize) {
+		self.set_histcur(self.histcur.saturating_add_signed(lines));
+	}
+
+	pub fn scroll_history_top(&mut self) {
+		self.set_histcur(self.history.len());
+	}
+
+	// blank cell for erasing, only the background of the pen is kept (BCE)
+	pub fn erase_cell(&self) -> Cell {
+		let bg = if self.reversed { self.ecell.fg } else { self.ecell.bg };
+		Cell { bg, ..Default::default() }
 	}
 
 	pub fn char_cell(&self, ch: u32) -> Cell {
@@ -155,14 +564,162 @@ impl WriteHalf {
 			let mut fg2 = cell.fg & 0xFF;
 			fg2 |= cell.bg & 0xFFFFFF00;
 			[cell.fg, cell.bg] = [fg2, cell.fg];
+			cell.de |= DE_REVERSED;
 		}
 		cell
 	}
 
+	// ICH, shift right inside the margins
+	pub fn ich(&mut self, count: i16) {
+		let [left, right] = self.margins;
+		let cx = self.cursor[0] as usize;
+		if cx < left || cx >= right {
+			return
+		}
+		let count = (count as usize).min(right - cx);
+		let blank = self.erase_cell();
+		let row = &mut self.buffer[self.cursor[1] as usize];
+		row[cx..right].rotate_right(count);
+		row[cx..cx + count].fill(blank);
+		self.include_damage(Region::new([
+			cx as i16,
+			self.cursor[1],
+			right as i16,
+			self.cursor[1] + 1,
+		]));
+	}
+
+	// DCH, shift left inside the margins
+	pub fn dch(&mut self, count: i16) {
+		let [left, right] = self.margins;
+		let cx = self.cursor[0] as usize;
+		if cx < left || cx >= right {
+			return
+		}
+		let count = (count as usize).min(right - cx);
+		let blank = self.erase_cell();
+		let row = &mut self.buffer[self.cursor[1] as usize];
+		row[cx..right].rotate_left(count);
+		row[right - count..right].fill(blank);
+		self.include_damage(Region::new([
+			cx as i16,
+			self.cursor[1],
+			right as i16,
+			self.cursor[1] + 1,
+		]));
+	}
+
+	// [x0, y0, x1, y1) clamped to the screen, None if empty
+	fn clamp_rect(&self, rect: [i16; 4]) -> Option<[usize; 4]> {
+		let x0 = rect[0].max(0);
+		let y0 = rect[1].max(0);
+		let x1 = rect[2].min(self.size[0]);
+		let y1 = rect[3].min(self.size[1]);
+		if x0 >= x1 || y0 >= y1 {
+			return None
+		}
+		Some([x0 as usize, y0 as usize, x1 as usize, y1 as usize])
+	}
+
+	fn rect_damage(&mut self, rect: [usize; 4]) {
+		let [x0, y0, x1, y1] = rect.map(|x| x as i16);
+		self.include_damage(Region::new([x0, y0, x1, y1]));
+	}
+
+	// DECFRA with Some(ch), DECERA with None
+	pub fn fill_rect(&mut self, rect: [i16; 4], ch: Option<u32>) {
+		let rect = match self.clamp_rect(rect) {
+			Some(rect) => rect,
+			None => return,
+		};
+		let cell = match ch {
+			Some(ch) => self.char_cell(ch),
+			None => self.erase_cell(),
+		};
+		let [x0, y0, x1, y1] = rect;
+		for row in self.buffer[y0..y1].iter_mut() {
+			row[x0..x1].fill(cell);
+		}
+		self.rect_damage(rect);
+	}
+
+	// DECCRA
+	pub fn copy_rect(&mut self, src: [i16; 4], dst: [i16; 2]) {
+		let [x0, y0, x1, y1] = match self.clamp_rect(src) {
+			Some(rect) => rect,
+			None => return,
+		};
+		let copied: Vec<Vec<Cell>> = self.buffer[y0..y1].iter()
+			.map(|row| row[x0..x1].to_vec())
+			.collect();
+		let [dx, dy] = dst;
+		let dst = [
+			dx,
+			dy,
+			dx.saturating_add((x1 - x0) as i16),
+			dy.saturating_add((y1 - y0) as i16),
+		];
+		let rect = match self.clamp_rect(dst) {
+			Some(rect) => rect,
+			None => return,
+		};
+		let [x0, y0, x1, y1] = rect;
+		for (row, src) in self.buffer[y0..y1].iter_mut().zip(copied.iter()) {
+			row[x0..x1].copy_from_slice(&src[..x1 - x0]);
+		}
+		self.rect_damage(rect);
+	}
+
+	// DECCARA and DECRARA, ty as in set_decoration
+	pub fn change_rect_decoration(&mut self, rect: [i16; 4], mask: u32, ty: u8) {
+		let rect = match self.clamp_rect(rect) {
+			Some(rect) => rect,
+			None => return,
+		};
+		let [x0, y0, x1, y1] = rect;
+		for row in self.buffer[y0..y1].iter_mut() {
+			for cell in row[x0..x1].iter_mut() {
+				let changed = match ty {
+					0 => 0,
+					1 => mask,
+					_ => (cell.de & mask) ^ mask,
+				};
+				cell.de = (cell.de & !mask) | changed;
+			}
+		}
+		self.rect_damage(rect);
+	}
+
+	// DECCARA and DECRARA 7 and 27, ty as in set_decoration,
+	// colors are swapped where DE_REVERSED changes
+	pub fn reverse_rect(&mut self, rect: [i16; 4], ty: u8) {
+		let rect = match self.clamp_rect(rect) {
+			Some(rect) => rect,
+			None => return,
+		};
+		let [x0, y0, x1, y1] = rect;
+		for row in self.buffer[y0..y1].iter_mut() {
+			for cell in row[x0..x1].iter_mut() {
+				let reversed = cell.de & DE_REVERSED != 0;
+				let new = match ty {
+					0 => false,
+					1 => true,
+					_ => !reversed,
+				};
+				if new != reversed {
+					[cell.fg, cell.bg] = [cell.bg, cell.fg];
+					cell.de ^= DE_REVERSED;
+				}
+			}
+		}
+		self.rect_damage(rect);
+	}
+
 	pub fn ech(&mut self, count: i16) {
 		let xmax = (self.cursor[0] + count).min(self.size[0]);
+		let blank = self.erase_cell();
 		for x in self.cursor[0]..xmax {
-			self.buffer[self.cursor[1] as usize][x as usize] = self.ecell;
+			self.buffer[self.cursor[1] as usize][x as usize] = blank;
 		}
 		self.damage_all();
 		self.include_damage(Region::new([
@@ -175,18 +732,21 @@ impl WriteHalf {
 
 	pub fn put(&mut self, ch: char) {
 		let (wide, width) = wide_test(ch);
+		let [left, right] = self.line_bounds();
 		if self.eol {
 			self.eol = false;
+			self.buffer[self.cursor[1] as usize].wrapped = true;
 			self.newline();
-			self.loc(0, 0);
+			self.loc(0, left);
 		}
 
-		if self.cursor[0] == self.size[0] - 1 && wide {
+		if self.cursor[0] == right - 1 && wide {
+			self.buffer[self.cursor[1] as usize].wrapped = true;
 			self.newline();
-			self.loc(0, 0);
+			self.loc(0, left);
 		}
 
-		let new_eol = self.cursor[0] == self.size[0] - width;
+		let new_eol = self.cursor[0] == right - width;
 		let cx = self.cursor[0] as usize;
 		let cy = self.cursor[1] as usize;
 		let ch = ch as u32;
@@ -208,41 +768,61 @@ impl WriteHalf {
 		}
 	}
 
-	pub fn erase_display(&mut self, code: u16) {
+	// selective: protected cells are kept (DECSED, DECSEL)
+	fn erase_cells(&mut self, y: i16, x0: i16, x1: i16, selective: bool) {
+		let blank = self.erase_cell();
+		let row = &mut self.buffer[y as usize];
+		for cell in row[x0 as usize..x1 as usize].iter_mut() {
+			if selective && cell.de & DE_PROTECTED != 0 {
+				continue
+			}
+			*cell = blank;
+		}
+		if x1 == self.size[0] && !selective {
+			row.wrapped = false;
+		}
+	}
+
+	pub fn erase_display(&mut self, code: u16, selective: bool) {
 		let [begin, end] =match code {
 			0 => {
-				self.erase_line(0);
+				self.erase_line(0, selective);
 				[self.cursor[1] + 1, self.size[1]]
 			}
 			1 => {
-				self.erase_line(1);
+				self.erase_line(1, selective);
 				[0, self.cursor[1]]
 			}
 			_ => [0, self.size[1]],
 		};
 		for row in begin..end {
-			self.buffer[row as usize] = vec![self.ecell; self.size[0] as usize];
+			self.erase_cells(row, 0, self.size[0], selective);
 		}
+		// images over the partly erased cursor line go too
+		let first = self.abs_line(begin.min(self.cursor[1]));
+		let last = self.abs_line(end.max(self.cursor[1] + 1));
+		self.images.erase_lines(first, last);
 		self.include_damage(Region::new(
 			[0, begin, self.size[0], end]
 		));
 	}
 
-	pub fn erase_line(&mut self, code: u16) {
+	pub fn erase_line(&mut self, code: u16, selective: bool) {
 		let [begin, end] =match code {
 			0 => [self.cursor[0], self.size[0]],
 			1 => [0, self.cursor[0] + 1],
 			_ => [0, self.size[0]],
 		};
-		let row = &mut self.buffer[self.cursor[1] as usize];
-		for col in begin..end {
-			row[col as usize] = self.ecell;
-		}
+		self.erase_cells(self.cursor[1], begin, end, selective);
 		self.include_damage(Region::new(
 			[begin, self.cursor[1], end, self.cursor[1] + 1]
 		));
 	}
 
+	pub fn set_protected(&mut self, protected: bool) {
+		self.set_decoration(DE_PROTECTED, protected as u8);
+	}
+
 	pub fn fg_color(&mut self, color: u32) {
 		self.ecell.fg = color;
 	}
@@ -265,11 +845,34 @@ impl WriteHalf {
 			}
 			_ => unreachable!()
 		};
-		self.ecell.de = self.ecell.de & !mask + changed;
+		self.ecell.de = (self.ecell.de & !mask) | changed;
+	}
+
+	// style 0 = no underline, unknown styles are single
+	pub fn set_underline(&mut self, style: u16) {
+		self.set_decoration(DE_UNDERLINE | DE_ULSTYLE, 0);
+		if style == 0 {
+			return
+		}
+		let style = if style > 5 { 1 } else { style as u32 };
+		self.set_decoration(DE_UNDERLINE | style << DE_ULSTYLE_SHIFT, 1);
+	}
+
+	pub fn underline_color(&mut self, idx: Option<u8>) {
+		self.set_decoration(DE_ULCOLOR_SET | DE_ULCOLOR, 0);
+		if let Some(idx) = idx {
+			let bits = DE_ULCOLOR_SET | (idx as u32) << DE_ULCOLOR_SHIFT;
+			self.set_decoration(bits, 1);
+		}
+	}
+
+	pub fn carriage_return(&mut self) {
+		let [left, _] = self.line_bounds();
+		self.loc(0, left);
 	}
 
 	pub fn newline(&mut self) {
-		if self.cursor[1] == self.size[1] - 1 {
+		if self.cursor[1] as usize + 1 == self.scroll_region[1] {
 			self.scroll(true);
 		} else {
 			self.loc(3, 1);
@@ -294,21 +897,25 @@ impl WriteHalf {
 		self.writer.write(&[2])?;
 		area.write_le_bytes(&mut self.writer)?;
 		let area = area.data();
+		let view_top = self.view_top();
+		let indicator = self.indicator();
 
 		// respect to hist
 		for y in area[1] as usize..area[3] as usize {
+			//         <-----> s = 7
+			// scr:    xxxxxxx
+			// hst: xxxxxxx<-- h = 3
+			//        ^ y = 2, out
+			let row = if y < self.histcur {
+				let yy = self.histcur - y - 1;
+				Cow::Owned(self.history[yy].unpack())
+			} else {
+				let yy = y - self.histcur;
+				Cow::Borrowed(&self.buffer[yy])
+			};
 			for x in area[0] as usize..area[2] as usize {
-				//         <-----> s = 7
-				// scr:    xxxxxxx
-				// hst: xxxxxxx<-- h = 3
-				//        ^ y = 2, out
-				let cell = if y < self.histcur {
-					let yy = self.histcur - y - 1;
-					self.history[yy].get(x).cloned().unwrap_or(Cell::default())
-				} else {
-					let yy = y - self.histcur;
-					self.buffer[yy][x]
-				};
+				let cell = row.get(x).cloned().unwrap_or(Cell::default());
+				let cell = self.decorate(cell, view_top + y, [x, y], &indicator);
 
 				cell.write_le_bytes(&mut self.writer)?;
 			}
@@ -316,6 +923,22 @@ impl WriteHalf {
 		Ok(())
 	}
 
+	fn send_text(&mut self, ty: u8, text: &str) -> Result<()> {
+		self.writer.write_all(&[ty])?;
+		self.writer.write_all(&(text.len() as u32).to_le_bytes())?;
+		self.writer.write_all(text.as_bytes())?;
+		self.writer.flush()
+	}
+
+	pub fn send_clipboard(&mut self, text: &str) -> Result<()> {
+		self.send_text(6, text)
+	}
+
+	// ask the server to open a url or path
+	pub fn send_open(&mut self, target: &str) -> Result<()> {
+		self.send_text(7, target)
+	}
+
 	pub fn send_cursor(&mut self) -> Result<()> {
 		self.writer.write(&[0])?;
 		self.writer.write(&self.cursor[0].to_le_bytes())?;
@@ -336,6 +959,9 @@ impl WriteHalf {
 	pub fn send_damage(&mut self) -> Result<()> {
 		// eprintln!("send dmg {:?}", self.damage);
 		self.send_area(self.damage)?;
+		self.images.prune(self.hist_base);
+		let view_top = self.view_top();
+		self.images.write(&mut self.writer, view_top, self.size[1])?;
 		self.send_cursor()?;
 		self.writer.flush()?;
 		self.damage = Region::default();
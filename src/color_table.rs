@@ -47,4 +47,19 @@ impl ColorTable {
 	pub fn rgb_from_256color(&self, color: u8) -> u32 {
 		self.data[color as usize]
 	}
+
+	// closest 256 color index of an rgba color
+	pub fn nearest(&self, color: u32) -> u8 {
+		let channels = |c: u32| [c >> 24, (c >> 16) & 0xFF, (c >> 8) & 0xFF];
+		let target = channels(color);
+		let dist = |c: u32| {
+			channels(c).iter()
+				.zip(target.iter())
+				.map(|(a, b)| (*a as i32 - *b as i32).pow(2))
+				.sum::<i32>()
+		};
+		(0..self.data.len())
+			.min_by_key(|idx| dist(self.data[*idx]))
+			.unwrap_or(0) as u8
+	}
 }
@@ -0,0 +1,276 @@
+use skey::Sktype;
+
+use crate::client::{Mark, Overlay, WriteHalf};
+use crate::line::{cell_char, cell_width};
+
+const CURSOR_COLOR: Mark = Mark::Color(0x000000FF, 0x7FD88FFF);
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Kind {
+	Char,
+	Line,
+	Block,
+}
+
+// positions are [x, absolute line]
+pub struct Selection {
+	pub kind: Kind,
+	pub start: [usize; 2],
+	pub end: [usize; 2],
+}
+
+impl Selection {
+	// first and last position, in reading order
+	fn ordered(&self) -> ([usize; 2], [usize; 2]) {
+		let key = |p: &[usize; 2]| (p[1], p[0]);
+		if key(&self.start) <= key(&self.end) {
+			(self.start, self.end)
+		} else {
+			(self.end, self.start)
+		}
+	}
+
+	// selected columns of a line of len cells, x1 exclusive
+	fn range(&self, line: usize, len: usize) -> Option<(usize, usize)> {
+		let (a, b) = self.ordered();
+		if line < a[1] || line > b[1] {
+			return None
+		}
+		let range = match self.kind {
+			Kind::Char => {
+				let x0 = if line == a[1] { a[0] } else { 0 };
+				let x1 = if line == b[1] { b[0] + 1 } else { len };
+				(x0, x1)
+			}
+			Kind::Line => (0, len),
+			Kind::Block => {
+				let x0 = self.start[0].min(self.end[0]);
+				let x1 = self.start[0].max(self.end[0]) + 1;
+				(x0, x1)
+			}
+		};
+		Some((range.0.min(len), range.1.min(len)))
+	}
+
+	pub fn overlays(&self, cols: usize) -> Vec<Overlay> {
+		let (a, b) = self.ordered();
+		(a[1]..=b[1])
+			.filter_map(|line| {
+				let (x0, x1) = self.range(line, cols)?;
+				Some(Overlay { line, x0, x1, mark: Mark::Reverse })
+			})
+			.collect()
+	}
+
+	pub fn text(&self, wh: &WriteHalf) -> String {
+		let (a, b) = self.ordered();
+		let mut result = String::new();
+		for y in a[1]..=b[1] {
+			let line = match wh.get_line(y) {
+				Some(line) => line,
+				None => break,
+			};
+			let (x0, x1) = self.range(y, line.len()).unwrap();
+			let mut x = 0;
+			let begin = result.len();
+			while x < x1 {
+				let width = cell_width(&line[x]);
+				// a wide char is taken if any half is selected
+				if x + width > x0 {
+					result.push(cell_char(&line[x]));
+				}
+				x += width;
+			}
+			// a soft wrap joins with the next line
+			let soft = line.wrapped && self.kind != Kind::Block && x1 == line.len();
+			if !soft {
+				let len = begin + result[begin..].trim_end_matches(' ').len();
+				result.truncate(len);
+				if y != b[1] {
+					result.push('\n');
+				}
+			}
+		}
+		result
+	}
+}
+
+pub struct CopyMode {
+	// [x, absolute line]
+	cursor: [usize; 2],
+	selection: Option<Selection>,
+}
+
+impl CopyMode {
+	pub fn new(wh: &mut WriteHalf) -> Self {
+		let [x, y] = wh.cursor();
+		let result = Self {
+			cursor: [x as usize, wh.abs_line(y)],
+			selection: None,
+		};
+		result.update(wh);
+		result
+	}
+
+	fn update(&self, wh: &mut WriteHalf) {
+		let cols = wh.size()[0] as usize;
+		let mut overlays = match self.selection.as_ref() {
+			Some(selection) => selection.overlays(cols),
+			None => Vec::new(),
+		};
+		let [x, line] = self.cursor;
+		overlays.push(Overlay { line, x0: x, x1: x + 1, mark: CURSOR_COLOR });
+		wh.set_overlays(overlays);
+		let status = match self.selection.as_ref().map(|x| x.kind) {
+			None => "-- COPY --",
+			Some(Kind::Char) => "-- VISUAL --",
+			Some(Kind::Line) => "-- VISUAL LINE --",
+			Some(Kind::Block) => "-- VISUAL BLOCK --",
+		};
+		wh.set_status(Some(status));
+		wh.show_line(line);
+	}
+
+	fn move_to(&mut self, wh: &WriteHalf, x: usize, line: usize) {
+		let cols = wh.size()[0] as usize;
+		let line = line.clamp(wh.first_line(), wh.end_line() - 1);
+		self.cursor = [x.min(cols - 1), line];
+		if let Some(selection) = self.selection.as_mut() {
+			selection.end = self.cursor;
+		}
+	}
+
+	fn move_by(&mut self, wh: &WriteHalf, dx: isize, dy: isize) {
+		let x = self.cursor[0].saturating_add_signed(dx);
+		let line = self.cursor[1].saturating_add_signed(dy);
+		self.move_to(wh, x, line);
+	}
+
+	fn chars(wh: &WriteHalf, line: usize) -> Vec<char> {
+		match wh.get_line(line) {
+			Some(line) => line.iter().map(cell_char).collect(),
+			None => Vec::new(),
+		}
+	}
+
+	// first and last non-blank column
+	fn line_edge(&mut self, wh: &WriteHalf, last: bool) {
+		let chars = Self::chars(wh, self.cursor[1]);
+		let mut iter = chars.iter().enumerate().filter(|x| *x.1 != ' ');
+		let x = if last { iter.next_back() } else { iter.next() };
+		let x = x.map(|x| x.0).unwrap_or(0);
+		self.move_to(wh, x, self.cursor[1]);
+	}
+
+	// start of the next or previous word, blank lines are skipped
+	fn word(&mut self, wh: &WriteHalf, forward: bool) {
+		let [mut x, mut line] = self.cursor;
+		// None: search the whole line
+		let mut from = Some(x);
+		loop {
+			let chars = Self::chars(wh, line);
+			let start = |i: usize| chars[i] != ' ' && (i == 0 || chars[i - 1] == ' ');
+			let found = if forward {
+				(from.map(|x| x + 1).unwrap_or(0)..chars.len()).find(|&i| start(i))
+			} else {
+				(0..from.unwrap_or(chars.len()).min(chars.len())).rev().find(|&i| start(i))
+			};
+			if let Some(found) = found {
+				x = found;
+				break
+			}
+			if forward && line + 1 < wh.end_line() {
+				line += 1;
+				x = 0;
+			} else if !forward && line > wh.first_line() {
+				line -= 1;
+				x = wh.size()[0] as usize - 1;
+			} else {
+				break
+			}
+			from = None;
+		}
+		self.move_to(wh, x, line);
+	}
+
+	fn select(&mut self, kind: Kind) {
+		match self.selection.as_ref().map(|x| x.kind) {
+			Some(old) if old == kind => self.selection = None,
+			Some(_) => self.selection.as_mut().unwrap().kind = kind,
+			None => self.selection = Some(Selection {
+				kind,
+				start: self.cursor,
+				end: self.cursor,
+			}),
+		}
+	}
+
+	fn yank(&self, wh: &mut WriteHalf) -> bool {
+		if let Some(selection) = self.selection.as_ref() {
+			let text = selection.text(wh);
+			wh.send_clipboard(&text).unwrap();
+		}
+		self.close(wh)
+	}
+
+	fn close(&self, wh: &mut WriteHalf) -> bool {
+		wh.set_overlays(Vec::new());
+		wh.set_status(None);
+		false
+	}
+
+	// vi and emacs keys, return false if copy mode is left
+	pub fn key(&mut self, wh: &mut WriteHalf, ch: u32, alt: bool) -> bool {
+		let half = wh.size()[1] as isize / 2;
+		let ch = match char::from_u32(ch) {
+			Some(ch) => ch,
+			None => return true,
+		};
+		match ch {
+			'w' if alt => return self.yank(wh),
+			'h' | '\x02' => self.move_by(wh, -1, 0),
+			'l' | '\x06' => self.move_by(wh, 1, 0),
+			'j' | '\x0e' => self.move_by(wh, 0, 1),
+			'k' | '\x10' => self.move_by(wh, 0, -1),
+			'0' | '\x01' => self.move_to(wh, 0, self.cursor[1]),
+			'^' => self.line_edge(wh, false),
+			'$' | '\x05' => self.line_edge(wh, true),
+			'w' => self.word(wh, true),
+			'b' => self.word(wh, false),
+			'g' => self.move_to(wh, 0, wh.first_line()),
+			'G' => self.move_to(wh, 0, wh.end_line() - 1),
+			'\x15' => self.move_by(wh, 0, -half),
+			'\x04' => self.move_by(wh, 0, half),
+			'v' | '\0' => self.select(Kind::Char),
+			'V' => self.select(Kind::Line),
+			'\x16' => self.select(Kind::Block),
+			'y' | '\r' => return self.yank(wh),
+			'\x1b' | '\x07' => {
+				if self.selection.is_none() {
+					return self.close(wh)
+				}
+				self.selection = None;
+			}
+			'q' => return self.close(wh),
+			_ => {}
+		}
+		self.update(wh);
+		true
+	}
+
+	pub fn skey(&mut self, wh: &mut WriteHalf, ty: &Sktype) {
+		let rows = wh.size()[1] as isize;
+		match *ty {
+			Sktype::Direction(0) => self.move_by(wh, -1, 0),
+			Sktype::Direction(1) => self.move_by(wh, 0, -1),
+			Sktype::Direction(2) => self.move_by(wh, 1, 0),
+			Sktype::Direction(3) => self.move_by(wh, 0, 1),
+			Sktype::Direction(4) => self.move_to(wh, 0, self.cursor[1]),
+			Sktype::Direction(5) => self.line_edge(wh, true),
+			Sktype::Direction(6) => self.move_by(wh, 0, -rows),
+			Sktype::Direction(7) => self.move_by(wh, 0, rows),
+			_ => return,
+		}
+		self.update(wh);
+	}
+}
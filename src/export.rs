@@ -0,0 +1,200 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, ErrorKind, Result, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::client::{WriteHalf, DE_ULSTYLE, DE_ULSTYLE_SHIFT, DE_UNDERLINE};
+use crate::line::{cell_char, cell_width, is_blank, Line};
+use vkot_common::cell::Cell;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+	Text,
+	// replayable with cat
+	Ansi,
+	// standalone, inline styles
+	Html,
+}
+
+impl Format {
+	fn extension(&self) -> &'static str {
+		match self {
+			Format::Text => "txt",
+			Format::Ansi => "ansi",
+			Format::Html => "html",
+		}
+	}
+}
+
+// fg, bg, underline style
+type Style = (u32, u32, u32);
+
+const DEFAULT_STYLE: Style = (u32::MAX, 0, 0);
+
+fn style(cell: &Cell) -> Style {
+	let underline = if cell.de & DE_UNDERLINE != 0 {
+		((cell.de & DE_ULSTYLE) >> DE_ULSTYLE_SHIFT).max(1)
+	} else {
+		0
+	};
+	(cell.fg, cell.bg, underline)
+}
+
+fn rgb(color: u32) -> [u8; 3] {
+	[(color >> 24) as u8, (color >> 16) as u8, (color >> 8) as u8]
+}
+
+fn sgr(style: Style) -> String {
+	let mut result = String::from("\x1b[0");
+	if style.0 != DEFAULT_STYLE.0 {
+		let [r, g, b] = rgb(style.0);
+		result += &format!(";38;2;{};{};{}", r, g, b);
+	}
+	if style.1 != DEFAULT_STYLE.1 {
+		let [r, g, b] = rgb(style.1);
+		result += &format!(";48;2;{};{};{}", r, g, b);
+	}
+	match style.2 {
+		0 => {}
+		1 => result += ";4",
+		x => result += &format!(";4:{}", x),
+	}
+	result + "m"
+}
+
+fn css(style: Style) -> String {
+	let mut result = String::new();
+	if style.0 != DEFAULT_STYLE.0 {
+		let [r, g, b] = rgb(style.0);
+		result += &format!("color:#{:02x}{:02x}{:02x};", r, g, b);
+	}
+	if style.1 != DEFAULT_STYLE.1 {
+		let [r, g, b] = rgb(style.1);
+		result += &format!("background:#{:02x}{:02x}{:02x};", r, g, b);
+	}
+	let line = match style.2 {
+		0 => "",
+		2 => "underline double",
+		3 => "underline wavy",
+		4 => "underline dotted",
+		5 => "underline dashed",
+		_ => "underline",
+	};
+	if !line.is_empty() {
+		result += &format!("text-decoration:{};", line);
+	}
+	result
+}
+
+fn set_style<W: Write>(writer: &mut W, format: Format, old: Style, new: Style) -> Result<()> {
+	match format {
+		Format::Text => {}
+		Format::Ansi => write!(writer, "{}", sgr(new))?,
+		Format::Html => {
+			if old != DEFAULT_STYLE {
+				write!(writer, "</span>")?;
+			}
+			if new != DEFAULT_STYLE {
+				write!(writer, "<span style=\"{}\">", css(new))?;
+			}
+		}
+	}
+	Ok(())
+}
+
+fn write_char<W: Write>(writer: &mut W, format: Format, ch: char) -> Result<()> {
+	match (format, ch) {
+		(Format::Html, '<') => write!(writer, "&lt;"),
+		(Format::Html, '>') => write!(writer, "&gt;"),
+		(Format::Html, '&') => write!(writer, "&amp;"),
+		_ => write!(writer, "{}", ch),
+	}
+}
+
+// soft-wrapped lines are joined
+pub fn write_lines<W: Write>(
+	writer: &mut W,
+	lines: impl Iterator<Item = Line>,
+	format: Format,
+) -> Result<()> {
+	if format == Format::Html {
+		writeln!(writer, "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"></head>")?;
+		write!(writer, "<body style=\"background:#000;color:#fff\"><pre>")?;
+	}
+	let mut current = DEFAULT_STYLE;
+	for line in lines {
+		let mut len = line.len();
+		if !line.wrapped {
+			while len > 0 && is_blank(&line[len - 1]) {
+				len -= 1;
+			}
+		}
+		let mut x = 0;
+		while x < len {
+			let new = style(&line[x]);
+			if new != current {
+				set_style(writer, format, current, new)?;
+				current = new;
+			}
+			write_char(writer, format, cell_char(&line[x]))?;
+			x += cell_width(&line[x]);
+		}
+		if !line.wrapped {
+			// colors must not run into the next line
+			if current != DEFAULT_STYLE {
+				set_style(writer, format, current, DEFAULT_STYLE)?;
+				current = DEFAULT_STYLE;
+			}
+			writeln!(writer)?;
+		}
+	}
+	if current != DEFAULT_STYLE {
+		set_style(writer, format, current, DEFAULT_STYLE)?;
+	}
+	if format == Format::Html {
+		writeln!(writer, "</pre></body></html>")?;
+	}
+	Ok(())
+}
+
+// into VKOT_EXPORT_DIR or the temp dir, return the path written
+pub fn export_file(wh: &WriteHalf, format: Format) -> Result<PathBuf> {
+	let dir = match std::env::var("VKOT_EXPORT_DIR") {
+		Ok(x) => PathBuf::from(x),
+		Err(_) => std::env::temp_dir(),
+	};
+	let secs = SystemTime::now()
+		.duration_since(SystemTime::UNIX_EPOCH)
+		.map(|x| x.as_secs())
+		.unwrap_or(0);
+	let (path, file) = create_new(&dir, &format!("vkot-{}", secs), format.extension())?;
+	let mut writer = BufWriter::new(file);
+	wh.export(&mut writer, format)?;
+	writer.flush()?;
+	Ok(path)
+}
+
+// never follow or truncate an existing file, add a suffix instead,
+// only readable by the user since history may hold secrets
+fn create_new(dir: &Path, stem: &str, ext: &str) -> Result<(PathBuf, File)> {
+	let mut n = 0;
+	loop {
+		let name = if n == 0 {
+			format!("{}.{}", stem, ext)
+		} else {
+			format!("{}-{}.{}", stem, n, ext)
+		};
+		let path = dir.join(name);
+		let file = OpenOptions::new()
+			.write(true)
+			.create_new(true)
+			.mode(0o600)
+			.open(&path);
+		match file {
+			Ok(file) => return Ok((path, file)),
+			Err(e) if e.kind() == ErrorKind::AlreadyExists && n < 100 => n += 1,
+			Err(e) => return Err(e),
+		}
+	}
+}
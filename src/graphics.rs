@@ -0,0 +1,312 @@
+use std::fs::OpenOptions;
+use std::io::Read;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+
+use crate::client::WriteHalf;
+use crate::image::{Image, MAX_BYTES};
+
+// base64 of the largest image kept, with some room for whitespace
+const MAX_PAYLOAD: usize = MAX_BYTES / 3 * 4 + 4096;
+// width and height limit, as for sixel
+const MAX_SIZE: u32 = 4096;
+
+fn size_ok(width: u32, height: u32) -> bool {
+	(1..=MAX_SIZE).contains(&width) && (1..=MAX_SIZE).contains(&height)
+}
+
+fn base64_value(b: u8) -> Option<u32> {
+	let result = match b {
+		b'A'..=b'Z' => b - b'A',
+		b'a'..=b'z' => b - b'a' + 26,
+		b'0'..=b'9' => b - b'0' + 52,
+		b'+' | b'-' => 62,
+		b'/' | b'_' => 63,
+		_ => return None,
+	};
+	Some(result as u32)
+}
+
+// padding and whitespace are skipped
+pub fn base64_decode(data: &[u8]) -> Option<Vec<u8>> {
+	let mut result = Vec::with_capacity(data.len() * 3 / 4);
+	let mut acc = 0u32;
+	let mut bits = 0;
+	for b in data.iter() {
+		if *b == b'=' || b.is_ascii_whitespace() {
+			continue
+		}
+		acc = (acc << 6) | base64_value(*b)?;
+		bits += 6;
+		if bits >= 8 {
+			bits -= 8;
+			result.push((acc >> bits) as u8);
+			acc &= (1 << bits) - 1;
+		}
+	}
+	Some(result)
+}
+
+pub fn decode_png(data: &[u8]) -> Option<Image> {
+	let mut decoder = png::Decoder::new(data);
+	decoder.set_transformations(
+		png::Transformations::EXPAND | png::Transformations::STRIP_16
+	);
+	let mut reader = decoder.read_info().ok()?;
+	if !size_ok(reader.info().width, reader.info().height) {
+		return None
+	}
+	let mut buf = vec![0; reader.output_buffer_size()];
+	let info = reader.next_frame(&mut buf).ok()?;
+	let buf = &buf[..info.buffer_size()];
+	let data = match info.color_type {
+		png::ColorType::Rgba => buf.to_vec(),
+		png::ColorType::Rgb => decode_raw(buf, 3)?,
+		png::ColorType::GrayscaleAlpha => buf.chunks_exact(2)
+			.flat_map(|x| [x[0], x[0], x[0], x[1]])
+			.collect(),
+		png::ColorType::Grayscale => buf.iter()
+			.flat_map(|x| [*x, *x, *x, 255])
+			.collect(),
+		png::ColorType::Indexed => return None,
+	};
+	Some(Image {
+		width: info.width,
+		height: info.height,
+		data,
+	})
+}
+
+// rgb or rgba to rgba
+fn decode_raw(data: &[u8], channels: usize) -> Option<Vec<u8>> {
+	if channels == 4 {
+		return Some(data.to_vec())
+	}
+	let result = data.chunks_exact(channels)
+		.flat_map(|x| [x[0], x[1], x[2], 255])
+		.collect();
+	Some(result)
+}
+
+// key=value pairs of a kitty graphics command
+struct KittyCmd {
+	keys: Vec<(u8, Vec<u8>)>,
+}
+
+impl KittyCmd {
+	fn parse(control: &[u8]) -> Self {
+		let keys = control.split(|x| *x == b',')
+			.filter_map(|kv| {
+				if kv.len() < 2 || kv[1] != b'=' {
+					return None
+				}
+				Some((kv[0], kv[2..].to_vec()))
+			})
+			.collect();
+		Self { keys }
+	}
+
+	fn get(&self, key: u8) -> Option<&[u8]> {
+		self.keys.iter().find(|x| x.0 == key).map(|x| &x.1[..])
+	}
+
+	fn char(&self, key: u8, default: u8) -> u8 {
+		self.get(key).and_then(|x| x.first().cloned()).unwrap_or(default)
+	}
+
+	fn num(&self, key: u8) -> u32 {
+		self.get(key)
+			.and_then(|x| std::str::from_utf8(x).ok())
+			.and_then(|x| x.parse().ok())
+			.unwrap_or(0)
+	}
+}
+
+// only regular files outside of /proc, /sys and /dev, like kitty
+fn read_file(path: &Path) -> Result<Vec<u8>, &'static str> {
+	let path = std::fs::canonicalize(path).map_err(|_| "EBADF:cannot read file")?;
+	if ["/proc", "/sys", "/dev"].iter().any(|x| path.starts_with(x)) {
+		return Err("EPERM:special file")
+	}
+	let meta = std::fs::metadata(&path).map_err(|_| "EBADF:cannot read file")?;
+	if !meta.is_file() {
+		return Err("EINVAL:not a regular file")
+	}
+	// nonblocking, in case it was replaced by a fifo since the check
+	let file = OpenOptions::new()
+		.read(true)
+		.custom_flags(libc::O_NONBLOCK)
+		.open(&path)
+		.map_err(|_| "EBADF:cannot read file")?;
+	let mut data = Vec::new();
+	file.take(MAX_BYTES as u64 + 1)
+		.read_to_end(&mut data)
+		.map_err(|_| "EBADF:cannot read file")?;
+	if data.len() > MAX_BYTES {
+		return Err("EFBIG:file too large")
+	}
+	Ok(data)
+}
+
+#[derive(Default)]
+pub struct KittyGraphics {
+	// command and payload of an unfinished chunked transmission,
+	// the payload is dropped once it grows over MAX_PAYLOAD
+	pending: Option<(KittyCmd, Option<Vec<u8>>)>,
+}
+
+impl KittyGraphics {
+	// data is the APC string without the leading 'G', return the reply
+	pub fn command(&mut self, data: &[u8], wh: &mut WriteHalf) -> Vec<u8> {
+		let (control, payload) = match data.iter().position(|x| *x == b';') {
+			Some(idx) => (&data[..idx], &data[idx + 1..]),
+			None => (data, &data[data.len()..]),
+		};
+		let chunk = KittyCmd::parse(control);
+		let more = chunk.num(b'm') == 1;
+		let (cmd, buf) = match self.pending.take() {
+			Some((cmd, buf)) => (cmd, buf.map(|mut buf| {
+				buf.extend_from_slice(payload);
+				buf
+			})),
+			None => (chunk, Some(payload.to_vec())),
+		};
+		let buf = buf.filter(|x| x.len() <= MAX_PAYLOAD);
+		if more {
+			self.pending = Some((cmd, buf));
+			return Vec::new()
+		}
+		let result = match buf {
+			Some(buf) => Self::execute(&cmd, &buf, wh),
+			None => Err("EFBIG:payload too large"),
+		};
+		let id = cmd.num(b'i');
+		let quiet = cmd.num(b'q');
+		if id == 0 || quiet >= 2 || (quiet == 1 && result.is_ok()) {
+			return Vec::new()
+		}
+		let mut reply = format!("\x1b_Gi={}", id);
+		let pid = cmd.num(b'p');
+		if pid != 0 {
+			reply += &format!(",p={}", pid);
+		}
+		match result {
+			Ok(()) => reply += ";OK",
+			Err(e) => reply += &format!(";{}", e),
+		}
+		reply += "\x1b\\";
+		reply.into_bytes()
+	}
+
+	fn load(cmd: &KittyCmd, payload: &[u8]) -> Result<Image, &'static str> {
+		let data = base64_decode(payload).ok_or("EINVAL:bad base64")?;
+		let data = match cmd.char(b't', b'd') {
+			b'd' => data,
+			b'f' => {
+				let path = String::from_utf8_lossy(&data).to_string();
+				read_file(Path::new(&path))?
+			}
+			_ => return Err("ENOTSUPPORTED:transmission medium"),
+		};
+		let data = match cmd.char(b'o', 0) {
+			0 => data,
+			b'z' => miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(&data, MAX_BYTES)
+				.map_err(|_| "EINVAL:bad or too large zlib data")?,
+			_ => return Err("ENOTSUPPORTED:compression"),
+		};
+		let format = match cmd.num(b'f') {
+			0 => 32,
+			x => x,
+		};
+		if format == 100 {
+			return decode_png(&data).ok_or("EBADPNG:cannot decode png")
+		}
+		let channels = match format {
+			24 => 3,
+			32 => 4,
+			_ => return Err("ENOTSUPPORTED:format"),
+		};
+		let [width, height] = [cmd.num(b's'), cmd.num(b'v')];
+		if !size_ok(width, height) {
+			return Err("EINVAL:bad image size")
+		}
+		if width as usize * height as usize * channels != data.len() {
+			return Err("ENODATA:size mismatch")
+		}
+		Ok(Image {
+			width,
+			height,
+			data: decode_raw(&data, channels).unwrap(),
+		})
+	}
+
+	fn execute(
+		cmd: &KittyCmd,
+		payload: &[u8],
+		wh: &mut WriteHalf,
+	) -> Result<(), &'static str> {
+		let id = cmd.num(b'i');
+		let pid = cmd.num(b'p');
+		let move_cursor = cmd.num(b'C') == 0;
+		match cmd.char(b'a', b't') {
+			b'q' => {
+				Self::load(cmd, payload)?;
+			}
+			b't' | b'T' => {
+				let image = Self::load(cmd, payload)?;
+				let key = if id == 0 { None } else { Some(id) };
+				let id = wh.images.add(key, image).ok_or("ENOSPC:image too large")?;
+				if cmd.char(b'a', b't') == b'T' {
+					wh.place_kitty(id, pid, move_cursor);
+				}
+			}
+			b'p' => {
+				if wh.images.get(id).is_none() {
+					return Err("ENOENT:image not found")
+				}
+				wh.place_kitty(id, pid, move_cursor);
+			}
+			b'd' => match cmd.char(b'd', b'a') {
+				b'a' => wh.images.clear_placements(),
+				b'A' => wh.images.clear(),
+				b'i' => wh.images.remove_placement(id, pid),
+				b'I' => wh.images.remove(id),
+				x => {
+					eprintln!("uh kitty graphics delete {:?}", x as char);
+					return Err("ENOTSUPPORTED:delete target")
+				}
+			}
+			x => {
+				eprintln!("uh kitty graphics action {:?}", x as char);
+				return Err("ENOTSUPPORTED:action")
+			}
+		}
+		Ok(())
+	}
+}
+
+// OSC 1337 File=, params are the ;-separated parts after 1337
+pub fn iterm_file(params: &[&[u8]], wh: &mut WriteHalf) {
+	let data = params.join(&b';');
+	let (header, payload) = match data.iter().position(|x| *x == b':') {
+		Some(idx) => (&data[..idx], &data[idx + 1..]),
+		None => return,
+	};
+	let header = match header.strip_prefix(b"File=") {
+		Some(header) => header,
+		None => {
+			eprintln!("uh osc 1337 {}", String::from_utf8_lossy(header));
+			return
+		}
+	};
+	// only inline images are displayed, downloads are ignored
+	let inline = header.split(|x| *x == b';').any(|x| x == b"inline=1");
+	if !inline {
+		return
+	}
+	let image = base64_decode(payload).and_then(|data| decode_png(&data));
+	match image {
+		Some(image) => wh.place_image(image),
+		None => eprintln!("osc 1337: cannot decode image"),
+	}
+}
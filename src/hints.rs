@@ -0,0 +1,192 @@
+use regex::Regex;
+use vkot_common::cell::Cell;
+
+use crate::client::{Mark, Overlay, WriteHalf};
+use crate::search::{texts, Span};
+
+const ALPHABET: &[u8] = b"jfkdlsaghurieowpq";
+const MATCH_COLOR: Mark = Mark::Color(0xFFFFFFFF, 0x3050A0FF);
+const LABEL_FG: u32 = 0x000000FF;
+const LABEL_BG: u32 = 0xFFD700FF;
+
+const DEFAULT_PATTERNS: &str = "\
+open (https?|ftp|file)://[^\\s<>\"'`]+
+open (~|\\.{1,2})?/?([\\w.-]+/)*[\\w.-]+\\.\\w+:\\d+(:\\d+)?
+copy \\b[0-9a-f]{7,40}\\b
+copy \\b\\d{1,3}(\\.\\d{1,3}){3}\\b";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Action {
+	Copy,
+	Open,
+}
+
+pub struct Pattern {
+	action: Action,
+	regex: Regex,
+}
+
+// VKOT_HINTS replaces the defaults, one "copy REGEX" or "open REGEX" per line
+pub fn patterns() -> Vec<Pattern> {
+	let config = match std::env::var("VKOT_HINTS") {
+		Ok(x) => x,
+		Err(_) => DEFAULT_PATTERNS.to_string(),
+	};
+	let mut result = Vec::new();
+	for line in config.lines() {
+		let (action, regex) = match line.split_once(' ') {
+			Some(x) => x,
+			None => continue,
+		};
+		let action = match action {
+			"copy" => Action::Copy,
+			"open" => Action::Open,
+			_ => {
+				eprintln!("uh hint action {:?}", action);
+				continue
+			}
+		};
+		match Regex::new(regex) {
+			Ok(regex) => result.push(Pattern { action, regex }),
+			Err(e) => eprintln!("bad hint regex {:?}: {}", regex, e),
+		}
+	}
+	result
+}
+
+struct Hint {
+	label: String,
+	text: String,
+	action: Action,
+	spans: Vec<Span>,
+}
+
+fn label(mut idx: usize, len: usize) -> String {
+	let mut result = vec![ALPHABET[0]; len];
+	for ch in result.iter_mut().rev() {
+		*ch = ALPHABET[idx % ALPHABET.len()];
+		idx /= ALPHABET.len();
+	}
+	String::from_utf8(result).unwrap()
+}
+
+pub struct Hints {
+	hints: Vec<Hint>,
+	typed: String,
+}
+
+impl Hints {
+	// None if nothing on screen matches
+	pub fn new(wh: &mut WriteHalf, patterns: &[Pattern]) -> Option<Self> {
+		let view_top = wh.view_top();
+		let rows = wh.size()[1] as usize;
+		let texts = texts(wh, view_top, view_top + rows);
+		let mut hints: Vec<Hint> = Vec::new();
+		// earlier patterns win on overlap
+		for pattern in patterns.iter() {
+			for text in texts.iter() {
+				for m in pattern.regex.find_iter(&text.text) {
+					let spans = text.spans(m.start(), m.end());
+					let (line, x, _) = match spans.first() {
+						Some(x) => *x,
+						None => continue,
+					};
+					let taken = hints.iter().any(|hint| hint.spans.iter()
+						.any(|s| s.0 == line && s.1 <= x && x < s.2));
+					if taken {
+						continue
+					}
+					hints.push(Hint {
+						label: String::new(),
+						text: m.as_str().to_string(),
+						action: pattern.action,
+						spans,
+					});
+				}
+			}
+		}
+		if hints.is_empty() {
+			return None
+		}
+		hints.sort_by_key(|x| (x.spans[0].0, x.spans[0].1));
+		let mut len = 1;
+		while ALPHABET.len().pow(len as u32) < hints.len() {
+			len += 1;
+		}
+		for (idx, hint) in hints.iter_mut().enumerate() {
+			hint.label = label(idx, len);
+		}
+		let result = Self { hints, typed: String::new() };
+		result.update(wh);
+		Some(result)
+	}
+
+	fn update(&self, wh: &mut WriteHalf) {
+		let mut overlays = Vec::new();
+		let mut labels = Vec::new();
+		let cell = Cell { fg: LABEL_FG, bg: LABEL_BG, ..Default::default() };
+		for hint in self.hints.iter() {
+			if !hint.label.starts_with(&self.typed) {
+				continue
+			}
+			for &(line, x0, x1) in hint.spans.iter() {
+				overlays.push(Overlay { line, x0, x1, mark: MATCH_COLOR });
+			}
+			let (line, x, _) = hint.spans[0];
+			for (idx, ch) in hint.label.chars().enumerate() {
+				let mark = Mark::Replace(cell.with_unic(ch as u32));
+				labels.push(Overlay { line, x0: x + idx, x1: x + idx + 1, mark });
+			}
+		}
+		// labels are drawn over matches
+		overlays.extend(labels);
+		wh.set_overlays(overlays);
+	}
+
+	// a label typed in upper case copies instead of opening
+	// return false if hints mode is left
+	pub fn key(&mut self, wh: &mut WriteHalf, ch: u32) -> bool {
+		let ch = match char::from_u32(ch) {
+			Some(ch) => ch,
+			None => return true,
+		};
+		match ch {
+			'\x1b' => return self.close(wh),
+			'\x7f' | '\x08' => {
+				self.typed.pop();
+			}
+			ch if ch.is_ascii_alphabetic() => {
+				self.typed.push(ch.to_ascii_lowercase());
+				// labels have the same length, the first found is the only one if complete
+				let found = self.hints.iter()
+					.find(|x| x.label.starts_with(&self.typed));
+				match found {
+					None => {
+						self.typed.pop();
+					}
+					Some(hint) if hint.label == self.typed => {
+						let action = if ch.is_ascii_uppercase() {
+							Action::Copy
+						} else {
+							hint.action
+						};
+						match action {
+							Action::Copy => wh.send_clipboard(&hint.text).unwrap(),
+							Action::Open => wh.send_open(&hint.text).unwrap(),
+						}
+						return self.close(wh)
+					}
+					Some(_) => {}
+				}
+			}
+			_ => {}
+		}
+		self.update(wh);
+		true
+	}
+
+	fn close(&self, wh: &mut WriteHalf) -> bool {
+		wh.set_overlays(Vec::new());
+		false
+	}
+}
@@ -0,0 +1,222 @@
+use std::collections::VecDeque;
+use std::io::{Result, Write};
+
+// total rgba bytes kept for all images
+pub const MAX_BYTES: usize = 256 << 20;
+// where allocation of ids not chosen by the application starts
+const AUTO_ID_BASE: u32 = 1 << 31;
+
+// rgba, row major
+pub struct Image {
+	pub width: u32,
+	pub height: u32,
+	pub data: Vec<u8>,
+}
+
+struct Placement {
+	id: u32,
+	pid: u32,
+	// absolute line number, see WriteHalf::abs_line
+	line: usize,
+	col: i16,
+	rows: usize,
+}
+
+pub struct ImageStore {
+	// oldest first, auto = id not chosen by the application,
+	// such an image can't be referenced once it has no placement
+	images: VecDeque<(u32, Image, bool)>,
+	placements: Vec<Placement>,
+	bytes: usize,
+	next_id: u32,
+	upload: Vec<u32>,
+	delete: Vec<u32>,
+	// (id, col, row) of the last placement list sent
+	sent: Vec<(u32, i16, i16)>,
+	// pixel size of a cell, for computing covered rows
+	pub cell_px: [i16; 2],
+}
+
+impl Default for ImageStore {
+	fn default() -> Self {
+		Self {
+			images: VecDeque::new(),
+			placements: Vec::new(),
+			bytes: 0,
+			next_id: AUTO_ID_BASE,
+			upload: Vec::new(),
+			delete: Vec::new(),
+			sent: Vec::new(),
+			cell_px: [8, 16],
+		}
+	}
+}
+
+impl ImageStore {
+	pub fn get(&self, id: u32) -> Option<&Image> {
+		self.images.iter().find(|x| x.0 == id).map(|x| &x.1)
+	}
+
+	// id = None: allocate one, return None if the image is too large
+	pub fn add(&mut self, id: Option<u32>, image: Image) -> Option<u32> {
+		let len = image.data.len();
+		let auto = id.is_none();
+		if len > MAX_BYTES {
+			eprintln!("image too large: {}x{}", image.width, image.height);
+			return None
+		}
+		let id = match id {
+			Some(id) => {
+				self.remove(id);
+				id
+			}
+			None => loop {
+				self.next_id = self.next_id.wrapping_add(1) | AUTO_ID_BASE;
+				if self.get(self.next_id).is_none() {
+					break self.next_id
+				}
+			}
+		};
+		while self.bytes + len > MAX_BYTES {
+			let old = self.images.front().unwrap().0;
+			self.remove(old);
+		}
+		self.bytes += len;
+		self.images.push_back((id, image, auto));
+		self.upload.push(id);
+		Some(id)
+	}
+
+	pub fn remove(&mut self, id: u32) {
+		let idx = match self.images.iter().position(|x| x.0 == id) {
+			Some(idx) => idx,
+			None => return,
+		};
+		let (_, image, _) = self.images.remove(idx).unwrap();
+		self.bytes -= image.data.len();
+		self.placements.retain(|x| x.id != id);
+		self.upload.retain(|x| *x != id);
+		self.delete.push(id);
+	}
+
+	pub fn clear(&mut self) {
+		while let Some(&(id, _, _)) = self.images.front() {
+			self.remove(id);
+		}
+	}
+
+	// return the number of columns and rows covered
+	pub fn place(&mut self, id: u32, pid: u32, line: usize, col: i16) -> [usize; 2] {
+		let (width, height) = match self.get(id) {
+			Some(image) => (image.width as usize, image.height as usize),
+			None => return [0, 0],
+		};
+		let cw = self.cell_px[0].max(1) as usize;
+		let ch = self.cell_px[1].max(1) as usize;
+		let cols = width.div_ceil(cw);
+		let rows = height.div_ceil(ch);
+		if pid != 0 {
+			self.placements.retain(|x| x.id != id || x.pid != pid);
+		}
+		self.placements.push(Placement { id, pid, line, col, rows });
+		[cols, rows]
+	}
+
+	pub fn clear_placements(&mut self) {
+		self.placements.clear();
+	}
+
+	pub fn remove_placement(&mut self, id: u32, pid: u32) {
+		self.placements.retain(|x| x.id != id || (pid != 0 && x.pid != pid));
+	}
+
+	// drop placements touching lines first..end
+	pub fn erase_lines(&mut self, first: usize, end: usize) {
+		self.placements.retain(|x| x.line + x.rows <= first || x.line >= end);
+	}
+
+	// the content of lines first..end moved by delta within them,
+	// drop placements crossing the edges or moved out
+	pub fn scroll_lines(&mut self, first: usize, end: usize, delta: isize) {
+		self.placements.retain_mut(|x| {
+			if x.line + x.rows <= first || x.line >= end {
+				return true
+			}
+			let line = x.line as isize + delta;
+			if x.line < first || x.line + x.rows > end ||
+				line < first as isize || line as usize + x.rows > end
+			{
+				return false
+			}
+			x.line = line as usize;
+			true
+		});
+	}
+
+	// lines from first on are numbered delta higher, content unchanged
+	pub fn renumber(&mut self, first: usize, delta: usize) {
+		for x in self.placements.iter_mut().filter(|x| x.line >= first) {
+			x.line += delta;
+		}
+	}
+
+	// drop placements scrolled out of history,
+	// and images that can no longer be referenced
+	pub fn prune(&mut self, first_line: usize) {
+		self.placements.retain(|x| x.line + x.rows > first_line);
+		let orphans: Vec<u32> = self.images.iter()
+			.filter(|x| x.2)
+			.map(|x| x.0)
+			.filter(|id| !self.placements.iter().any(|x| x.id == *id))
+			.collect();
+		for id in orphans.into_iter() {
+			self.remove(id);
+		}
+	}
+
+	// view_top: absolute line number of the first row on screen
+	pub fn write<W: Write>(
+		&mut self,
+		writer: &mut W,
+		view_top: usize,
+		rows: i16,
+	) -> Result<()> {
+		for id in self.delete.drain(..) {
+			writer.write_all(&[5])?;
+			writer.write_all(&id.to_le_bytes())?;
+		}
+		for id in std::mem::take(&mut self.upload).into_iter() {
+			let image = match self.get(id) {
+				Some(image) => image,
+				None => continue,
+			};
+			writer.write_all(&[3])?;
+			writer.write_all(&id.to_le_bytes())?;
+			writer.write_all(&image.width.to_le_bytes())?;
+			writer.write_all(&image.height.to_le_bytes())?;
+			writer.write_all(&image.data)?;
+			// placements of a reuploaded image must be resent
+			self.sent.clear();
+		}
+		let mut visible = Vec::new();
+		for placement in self.placements.iter() {
+			let y = placement.line as i64 - view_top as i64;
+			if y + placement.rows as i64 <= 0 || y >= rows as i64 {
+				continue
+			}
+			visible.push((placement.id, placement.col, y as i16));
+		}
+		if visible == self.sent {
+			return Ok(())
+		}
+		writer.write_all(&[4])?;
+		writer.write_all(&(visible.len() as u32).to_le_bytes())?;
+		for (id, x, y) in visible.iter() {
+			writer.write_all(&id.to_le_bytes())?;
+			writer.write_all(&x.to_le_bytes())?;
+			writer.write_all(&y.to_le_bytes())?;
+		}
+		self.sent = visible;
+		Ok(())
+	}
+}
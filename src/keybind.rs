@@ -0,0 +1,116 @@
+use skey::Sktype;
+
+use crate::export::Format;
+use crate::keyenc::Mods;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Action {
+	// positive = towards older lines
+	ScrollLines(isize),
+	ScrollPage(bool),
+	ScrollTop,
+	ScrollBottom,
+	Search,
+	CopyMode,
+	Hints,
+	Export(Format),
+}
+
+impl Action {
+	// only useful with history on the main screen
+	pub fn scrolls(&self) -> bool {
+		matches!(self, Action::ScrollLines(_) | Action::ScrollPage(_) |
+			Action::ScrollTop | Action::ScrollBottom)
+	}
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Key {
+	Direction(u8),
+	// matched in either case and as a control char,
+	// unless that is a key of its own
+	Letter(u8),
+	// mouse button, see ServerMsg::Mouse
+	Button(u8),
+}
+
+// shift, ctrl, key, action
+const TABLE: &[(bool, bool, Key, Action)] = &[
+	(true, false, Key::Direction(1), Action::ScrollLines(1)),
+	(true, false, Key::Direction(3), Action::ScrollLines(-1)),
+	(true, false, Key::Direction(4), Action::ScrollTop),
+	(true, false, Key::Direction(5), Action::ScrollBottom),
+	(true, false, Key::Direction(6), Action::ScrollPage(true)),
+	(true, false, Key::Direction(7), Action::ScrollPage(false)),
+	(false, false, Key::Button(4), Action::ScrollLines(3)),
+	(false, false, Key::Button(5), Action::ScrollLines(-3)),
+	(true, true, Key::Letter(b'f'), Action::Search),
+	(true, true, Key::Letter(b' '), Action::CopyMode),
+	(true, true, Key::Letter(b'u'), Action::Hints),
+	(true, true, Key::Letter(b'e'), Action::Export(Format::Text)),
+	(true, true, Key::Letter(b'a'), Action::Export(Format::Ansi)),
+	(true, true, Key::Letter(b'l'), Action::Export(Format::Html)),
+];
+
+// control chars also sent by backspace, tab, enter and escape
+const KEY_CODES: [u32; 4] = [0x08, 0x09, 0x0d, 0x1b];
+
+fn lookup(mods: Mods, pred: impl Fn(Key) -> bool) -> Option<Action> {
+	TABLE.iter()
+		.find(|x| x.0 == mods.shift && x.1 == mods.ctrl && pred(x.2))
+		.map(|x| x.3)
+}
+
+pub fn skey_action(ty: &Sktype, mods: Mods) -> Option<Action> {
+	match *ty {
+		Sktype::Direction(d) => lookup(mods, |key| key == Key::Direction(d)),
+		_ => None,
+	}
+}
+
+pub fn char_action(ch: u32, mods: Mods) -> Option<Action> {
+	lookup(mods, |key| match key {
+		Key::Letter(letter) => ch == letter as u32 ||
+			ch == letter.to_ascii_uppercase() as u32 ||
+			(ch == (letter & 0x1f) as u32 && !KEY_CODES.contains(&ch)),
+		_ => false,
+	})
+}
+
+// shift is ignored, it only bypasses mouse tracking
+pub fn mouse_action(button: u8, mut mods: Mods) -> Option<Action> {
+	mods.shift = false;
+	lookup(mods, |key| key == Key::Button(button))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const CTRL_SHIFT: Mods = Mods { shift: true, alt: false, ctrl: true };
+
+	#[test]
+	fn letters_as_char_and_control_byte() {
+		for &(_, _, key, action) in TABLE.iter() {
+			let letter = match key {
+				Key::Letter(x) => x,
+				_ => continue,
+			};
+			let control = (letter & 0x1f) as u32;
+			assert!(!KEY_CODES.contains(&control), "{:?} is taken", letter as char);
+			for ch in [letter, letter.to_ascii_uppercase()] {
+				assert!(char_action(ch as u32, CTRL_SHIFT) == Some(action));
+			}
+			assert!(char_action(control, CTRL_SHIFT) == Some(action));
+			let ctrl = Mods { ctrl: true, ..Default::default() };
+			assert!(char_action(control, ctrl).is_none());
+		}
+	}
+
+	#[test]
+	fn key_codes_pass_through() {
+		for ch in KEY_CODES.iter() {
+			assert!(char_action(*ch, CTRL_SHIFT).is_none());
+		}
+	}
+}
@@ -0,0 +1,200 @@
+use skey::{Skey, Sktype};
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Mods {
+	pub shift: bool,
+	pub alt: bool,
+	pub ctrl: bool,
+}
+
+impl Mods {
+	// xterm modifier parameter, 1 = no modifier
+	pub fn param(&self) -> u8 {
+		1 + self.shift as u8 + 2 * self.alt as u8 + 4 * self.ctrl as u8
+	}
+
+	pub fn any(&self) -> bool {
+		self.param() > 1
+	}
+}
+
+// (number, final byte) of the CSI sequence
+// direction: 0-3 = left, up, right, down, 4 = home, 5 = end,
+// 6 = pageup, 7 = pagedown, 8 = insert, 9 = delete
+// function: 1-12 = F1-F12
+fn key_code(ty: &Sktype) -> Option<(u8, u8)> {
+	let result = match *ty {
+		Sktype::Direction(x) => match x {
+			0 => (1, b'D'),
+			1 => (1, b'A'),
+			2 => (1, b'C'),
+			3 => (1, b'B'),
+			4 => (1, b'H'),
+			5 => (1, b'F'),
+			6 => (5, b'~'),
+			7 => (6, b'~'),
+			8 => (2, b'~'),
+			9 => (3, b'~'),
+			_ => return None,
+		}
+		Sktype::Function(x) => match x {
+			1 => (1, b'P'),
+			2 => (1, b'Q'),
+			3 => (1, b'R'),
+			4 => (1, b'S'),
+			5 => (15, b'~'),
+			6 => (17, b'~'),
+			7 => (18, b'~'),
+			8 => (19, b'~'),
+			9 => (20, b'~'),
+			10 => (21, b'~'),
+			11 => (23, b'~'),
+			12 => (24, b'~'),
+			_ => return None,
+		}
+		_ => return None,
+	};
+	Some(result)
+}
+
+// kitty keyboard protocol, progressive enhancement flags
+pub const KITTY_DISAMBIGUATE: u8 = 1;
+pub const KITTY_EVENT_TYPES: u8 = 2;
+pub const KITTY_ALL_KEYS: u8 = 8;
+pub const KITTY_TEXT: u8 = 16;
+
+// event: 1 = press, 3 = release
+fn kitty_seq(num: u8, fin: u8, mods: Mods, event: u8) -> Vec<u8> {
+	// F3 is CSI 13~ since CSI R is cursor position report
+	let (num, fin) = if fin == b'R' { (13, b'~') } else { (num, fin) };
+	let mut result = b"\x1b[".to_vec();
+	let params = mods.any() || event > 1;
+	if fin == b'~' || params {
+		result.extend(format!("{}", num).bytes());
+	}
+	if params {
+		result.extend(format!(";{}", mods.param()).bytes());
+		if event > 1 {
+			result.extend(format!(":{}", event).bytes());
+		}
+	}
+	result.push(fin);
+	result
+}
+
+fn legacy_seq(num: u8, fin: u8, mods: Mods, app_cursor: bool) -> Vec<u8> {
+	let mut result = Vec::new();
+	if fin == b'~' {
+		result.extend(format!("\x1b[{}", num).bytes());
+		if mods.any() {
+			result.extend(format!(";{}", mods.param()).bytes());
+		}
+	} else if mods.any() {
+		result.extend(format!("\x1b[1;{}", mods.param()).bytes());
+	} else if (b'P'..=b'S').contains(&fin) || app_cursor {
+		// F1-F4 are always SS3, cursor keys only in DECCKM
+		result.extend(b"\x1bO");
+	} else {
+		result.extend(b"\x1b[");
+	}
+	result.push(fin);
+	result
+}
+
+// bytes to send to the pty for a key event, None = nothing to send
+// text keys are sent through Getch, only their releases are reported here
+pub fn encode_skey(
+	skey: &Skey,
+	mods: Mods,
+	app_cursor: bool,
+	kitty: u8,
+) -> Option<Vec<u8>> {
+	let event = if skey.down { 1 } else { 3 };
+	if event == 3 && kitty & KITTY_EVENT_TYPES == 0 {
+		return None
+	}
+	let (num, fin) = match key_code(&skey.ty) {
+		Some(x) => x,
+		None => {
+			if let Sktype::Ascii(b) = skey.ty {
+				if event == 3 && kitty & KITTY_ALL_KEYS != 0 {
+					let code = b.to_ascii_lowercase();
+					let seq = format!("\x1b[{};{}:3u", code, mods.param());
+					return Some(seq.into_bytes())
+				}
+			}
+			return None
+		}
+	};
+	if kitty == 0 || (event == 1 && !mods.any() && kitty & KITTY_ALL_KEYS == 0) {
+		Some(legacy_seq(num, fin, mods, app_cursor))
+	} else {
+		Some(kitty_seq(num, fin, mods, event))
+	}
+}
+
+// CSI u encoding of text input, None = use the legacy encoding
+pub fn encode_char_kitty(ch: u32, mods: Mods, kitty: u8) -> Option<Vec<u8>> {
+	if kitty == 0 {
+		return None
+	}
+	let (code, text) = match ch {
+		// enter, tab and backspace keep their code with ctrl held
+		0x0d | 0x09 => (ch, false),
+		0x08 | 0x7f => (0x7f, false),
+		// ctrl+key, report the key so that ctrl+[ is not escape
+		0x00 if mods.ctrl => (0x20, false),
+		0x1b..=0x1f if mods.ctrl => (ch + 0x40, false),
+		0x01..=0x1a if mods.ctrl => (ch + 0x60, false),
+		0x1b => (ch, false),
+		0x00..=0x1f => return None,
+		_ => match char::from_u32(ch) {
+			Some(c) => (c.to_ascii_lowercase() as u32, true),
+			None => return None,
+		}
+	};
+	let all = kitty & KITTY_ALL_KEYS != 0;
+	// shift only changes the text, but makes enter, tab and backspace a new key
+	let plain = if text { !mods.ctrl && !mods.alt } else { !mods.any() };
+	// only disambiguate: unmodified keys stay legacy, except escape
+	if !all && plain && code != 0x1b {
+		return None
+	}
+	let with_text = all && text && plain && kitty & KITTY_TEXT != 0;
+	let mut result = format!("\x1b[{}", code).into_bytes();
+	if mods.any() || with_text {
+		result.extend(format!(";{}", mods.param()).bytes());
+	}
+	if with_text {
+		result.extend(format!(";{}", ch).bytes());
+	}
+	result.push(b'u');
+	Some(result)
+}
+
+// text input, alt is sent as ESC prefix when meta_esc(?1036) is set,
+// otherwise as the 8th bit (only possible for ascii)
+// backarrow_bs(DECBKM) turns DEL into BS
+pub fn encode_char(ch: u32, alt: bool, meta_esc: bool, backarrow_bs: bool) -> Vec<u8> {
+	let mut ch = match char::from_u32(ch) {
+		Some(ch) => ch,
+		None => {
+			eprintln!("bad char {:?}", ch);
+			return Vec::new()
+		}
+	};
+	if ch == '\x7f' && backarrow_bs {
+		ch = '\x08';
+	}
+	let mut result = Vec::new();
+	if alt {
+		if !meta_esc && ch.is_ascii() {
+			ch = char::from_u32(ch as u32 | 0x80).unwrap();
+		} else {
+			result.push(0x1b);
+		}
+	}
+	let mut buf = [0; 4];
+	result.extend(ch.encode_utf8(&mut buf).bytes());
+	result
+}
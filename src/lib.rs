@@ -4,3 +4,16 @@ pub mod color_table;
 pub mod ansiwrap;
 pub mod vte_actor;
 pub mod apaterm;
+pub mod keyenc;
+pub mod image;
+pub mod sixel;
+pub mod apc;
+pub mod graphics;
+pub mod mode;
+pub mod line;
+pub mod search;
+pub mod copymode;
+pub mod mouse;
+pub mod hints;
+pub mod keybind;
+pub mod export;
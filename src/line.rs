@@ -0,0 +1,268 @@
+use std::ops::{Deref, DerefMut};
+
+use vkot_common::cell::Cell;
+
+#[derive(Clone)]
+pub struct Line {
+	cells: Vec<Cell>,
+	// continues on the next line, set when put wraps at the end
+	pub wrapped: bool,
+}
+
+impl Line {
+	pub fn new(cell: Cell, len: usize) -> Self {
+		Self {
+			cells: vec![cell; len],
+			wrapped: false,
+		}
+	}
+}
+
+impl Deref for Line {
+	type Target = Vec<Cell>;
+
+	fn deref(&self) -> &Vec<Cell> {
+		&self.cells
+	}
+}
+
+impl DerefMut for Line {
+	fn deref_mut(&mut self) -> &mut Vec<Cell> {
+		&mut self.cells
+	}
+}
+
+// a line kept in history, trailing blanks are dropped
+// and attributes are stored as runs
+#[derive(Clone)]
+pub struct Packed {
+	chars: Box<[u32]>,
+	// count, fg, bg, de
+	runs: Box<[(u16, u32, u32, u32)]>,
+	// length before trimming
+	len: u16,
+	wrapped: bool,
+}
+
+impl Packed {
+	pub fn pack(line: &Line) -> Self {
+		let mut end = line.len();
+		while end > 0 && is_blank(&line[end - 1]) {
+			end -= 1;
+		}
+		let chars = line[..end].iter().map(|x| x.unic).collect();
+		let mut runs: Vec<(u16, u32, u32, u32)> = Vec::new();
+		for cell in line[..end].iter() {
+			match runs.last_mut() {
+				Some(run) if (run.1, run.2, run.3) == (cell.fg, cell.bg, cell.de) => run.0 += 1,
+				_ => runs.push((1, cell.fg, cell.bg, cell.de)),
+			}
+		}
+		Self {
+			chars,
+			runs: runs.into_boxed_slice(),
+			len: line.len() as u16,
+			wrapped: line.wrapped,
+		}
+	}
+
+	pub fn unpack(&self) -> Line {
+		let mut cells = Vec::with_capacity(self.len as usize);
+		let mut chars = self.chars.iter();
+		for &(count, fg, bg, de) in self.runs.iter() {
+			for ch in chars.by_ref().take(count as usize) {
+				let mut cell = Cell::default().with_unic(*ch);
+				cell.fg = fg;
+				cell.bg = bg;
+				cell.de = de;
+				cells.push(cell);
+			}
+		}
+		cells.resize(self.len as usize, Cell::default());
+		Line { cells, wrapped: self.wrapped }
+	}
+}
+
+// empty cells read as space
+pub fn cell_char(cell: &Cell) -> char {
+	match char::from_u32(cell.unic) {
+		Some('\0') | None => ' ',
+		Some(ch) => ch,
+	}
+}
+
+// number of columns taken, the cell after a wide char is left unused
+pub fn cell_width(cell: &Cell) -> usize {
+	match unicode_width::UnicodeWidthChar::width(cell_char(cell)) {
+		Some(2) => 2,
+		_ => 1,
+	}
+}
+
+pub fn is_blank(cell: &Cell) -> bool {
+	cell_char(cell) == ' ' && cell.bg == 0 && cell.de == 0
+}
+
+// split a logical line into rows of cols
+fn split(
+	cells: &[Cell],
+	cols: usize,
+	cursor_offset: Option<usize>,
+	result: &mut Vec<Line>,
+	cursor: &mut [usize; 2],
+) {
+	let blank = Cell::default();
+	let mut row: Vec<Cell> = Vec::with_capacity(cols);
+	for (idx, cell) in cells.iter().enumerate() {
+		let width = cell_width(cell);
+		if row.len() + width > cols && !row.is_empty() {
+			row.resize(cols, blank);
+			let cells = std::mem::replace(&mut row, Vec::with_capacity(cols));
+			result.push(Line { cells, wrapped: true });
+		}
+		if Some(idx) == cursor_offset {
+			*cursor = [row.len(), result.len()];
+		}
+		row.push(*cell);
+		if width == 2 && row.len() < cols {
+			row.push(blank);
+		}
+	}
+	row.resize(cols, blank);
+	result.push(Line { cells: row, wrapped: false });
+}
+
+// rewrap lines to a new width, cursor is [x, line index]
+// return the new lines and the cursor in them
+pub fn reflow(lines: Vec<Line>, cols: usize, cursor: [usize; 2]) -> (Vec<Line>, [usize; 2]) {
+	let mut result = Vec::new();
+	let mut new_cursor = [0, 0];
+	let mut logical: Vec<Cell> = Vec::new();
+	let mut cursor_offset = None;
+	for (idx, line) in lines.into_iter().enumerate() {
+		let mut len = line.len();
+		if !line.wrapped {
+			while len > 0 && is_blank(&line[len - 1]) {
+				len -= 1;
+			}
+		}
+		if idx == cursor[1] {
+			// keep the blanks up to the cursor
+			len = len.max(cursor[0] + 1).min(line.len());
+		}
+		let mut x = 0;
+		while x < len {
+			let width = cell_width(&line[x]);
+			if idx == cursor[1] && x <= cursor[0] && cursor[0] < x + width {
+				cursor_offset = Some(logical.len());
+			}
+			logical.push(line[x]);
+			x += width;
+		}
+		if !line.wrapped {
+			split(&logical, cols, cursor_offset, &mut result, &mut new_cursor);
+			logical.clear();
+			cursor_offset = None;
+		}
+	}
+	if !logical.is_empty() {
+		split(&logical, cols, cursor_offset, &mut result, &mut new_cursor);
+	}
+	(result, new_cursor)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn line(text: &str, cols: usize, wrapped: bool) -> Line {
+		let mut cells = Vec::new();
+		for ch in text.chars() {
+			cells.push(Cell::default().with_unic(ch as u32));
+			if cell_width(cells.last().unwrap()) == 2 {
+				cells.push(Cell::default());
+			}
+		}
+		cells.resize(cols, Cell::default());
+		Line { cells, wrapped }
+	}
+
+	// wrapped lines end with '+'
+	fn text(lines: &[Line]) -> Vec<String> {
+		lines.iter().map(|line| {
+			let mut result: String = line.iter().map(cell_char).collect();
+			if line.wrapped {
+				result.push('+');
+			}
+			result
+		}).collect()
+	}
+
+	#[test]
+	fn rewrap_narrower() {
+		let (lines, cursor) = reflow(vec![line("abcdef", 6, false)], 4, [5, 0]);
+		assert_eq!(text(&lines), ["abcd+", "ef  "]);
+		assert_eq!(cursor, [1, 1]);
+	}
+
+	#[test]
+	fn join_wrapped() {
+		let lines = vec![line("abcd", 4, true), line("ef", 4, false), line("g", 4, false)];
+		let (lines, cursor) = reflow(lines, 8, [1, 1]);
+		assert_eq!(text(&lines), ["abcdef  ", "g       "]);
+		assert_eq!(cursor, [5, 0]);
+	}
+
+	#[test]
+	fn wide_at_boundary() {
+		let (lines, cursor) = reflow(vec![line("ab中", 4, false)], 3, [2, 0]);
+		assert_eq!(text(&lines), ["ab +", "中  "]);
+		assert_eq!(cursor, [0, 1]);
+	}
+
+	#[test]
+	fn cursor_on_wide_filler() {
+		let (lines, cursor) = reflow(vec![line("中x", 3, false)], 2, [1, 0]);
+		assert_eq!(text(&lines), ["中 +", "x "]);
+		assert_eq!(cursor, [0, 0]);
+	}
+
+	#[test]
+	fn blanks_kept_up_to_cursor() {
+		let (lines, cursor) = reflow(vec![line("ab", 8, false)], 4, [5, 0]);
+		assert_eq!(text(&lines), ["ab  +", "    "]);
+		assert_eq!(cursor, [1, 1]);
+		let (lines, _) = reflow(vec![line("ab", 8, false), line("", 8, false)], 4, [0, 1]);
+		assert_eq!(text(&lines), ["ab  ", "    "]);
+	}
+
+	#[test]
+	fn pending_wrap_cursor() {
+		// cursor on the last column, as left by a pending wrap
+		let (lines, cursor) = reflow(vec![line("abcd", 4, false)], 6, [3, 0]);
+		assert_eq!(text(&lines), ["abcd  "]);
+		assert_eq!(cursor, [3, 0]);
+		let (lines, cursor) = reflow(vec![line("abcd", 4, false)], 2, [3, 0]);
+		assert_eq!(text(&lines), ["ab+", "cd"]);
+		assert_eq!(cursor, [1, 1]);
+	}
+
+	#[test]
+	fn split_wide_wider_than_cols() {
+		let cells = line("中", 2, false);
+		let mut result = Vec::new();
+		let mut cursor = [9, 9];
+		split(&cells[..1], 1, Some(0), &mut result, &mut cursor);
+		assert_eq!(text(&result), ["中"]);
+		assert_eq!(cursor, [0, 0]);
+	}
+
+	#[test]
+	fn pack_roundtrip() {
+		let mut source = line("a b  ", 8, true);
+		source[2].bg = 0x102030FF;
+		let packed = Packed::pack(&source).unpack();
+		assert_eq!(text(&[packed.clone()]), text(&[source]));
+		assert_eq!(packed[2].bg, 0x102030FF);
+	}
+}
@@ -0,0 +1,117 @@
+// ANSI and DEC private modes for SM/RM and DECRQM
+
+pub const DECCKM: u16 = 1;
+pub const DECBKM: u16 = 67;
+pub const DECLRMM: u16 = 69;
+pub const MOUSE_X10: u16 = 9;
+pub const MOUSE_CLICK: u16 = 1000;
+pub const MOUSE_DRAG: u16 = 1002;
+pub const MOUSE_MOTION: u16 = 1003;
+pub const FOCUS: u16 = 1004;
+pub const MOUSE_SGR: u16 = 1006;
+pub const META_ESC: u16 = 1036;
+pub const SYNC: u16 = 2026;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+	Changeable,
+	// value is tracked but has no effect on screen,
+	// so it's reported as not recognized,
+	// e.g. alt screen only switches the kitty keyboard stack
+	Tracked,
+	PermanentSet,
+	PermanentReset,
+}
+
+struct Entry {
+	private: bool,
+	num: u16,
+	kind: Kind,
+	value: bool,
+}
+
+const TABLE: &[(bool, u16, Kind, bool)] = &[
+	(false, 2, Kind::PermanentReset, false), // KAM
+	(false, 4, Kind::PermanentReset, false), // IRM
+	(false, 12, Kind::PermanentSet, true), // SRM
+	(false, 20, Kind::PermanentReset, false), // LNM
+	(true, DECCKM, Kind::Changeable, false),
+	(true, 3, Kind::PermanentReset, false), // DECCOLM
+	(true, 4, Kind::PermanentReset, false), // DECSCLM
+	(true, 5, Kind::PermanentReset, false), // DECSCNM
+	(true, 6, Kind::PermanentReset, false), // DECOM
+	(true, 7, Kind::PermanentSet, true), // DECAWM
+	(true, 8, Kind::PermanentSet, true), // DECARM
+	(true, MOUSE_X10, Kind::Changeable, false),
+	(true, 12, Kind::Tracked, false), // cursor blink
+	(true, 25, Kind::PermanentSet, true), // DECTCEM
+	(true, 47, Kind::Tracked, false), // alt screen
+	(true, 66, Kind::PermanentReset, false), // DECNKM
+	(true, DECBKM, Kind::Changeable, false),
+	(true, DECLRMM, Kind::Changeable, false),
+	(true, MOUSE_CLICK, Kind::Changeable, false),
+	(true, MOUSE_DRAG, Kind::Changeable, false),
+	(true, MOUSE_MOTION, Kind::Changeable, false),
+	(true, FOCUS, Kind::Changeable, false),
+	(true, 1005, Kind::PermanentReset, false), // utf8 mouse
+	(true, MOUSE_SGR, Kind::Changeable, false),
+	(true, 1015, Kind::PermanentReset, false), // urxvt mouse
+	(true, META_ESC, Kind::Changeable, true),
+	(true, 1047, Kind::Tracked, false), // alt screen
+	(true, 1048, Kind::Tracked, false), // save cursor
+	(true, 1049, Kind::Tracked, false), // alt screen + save cursor
+	(true, 2004, Kind::Tracked, false), // bracketed paste
+	(true, SYNC, Kind::Changeable, false),
+];
+
+pub struct Modes {
+	entries: Vec<Entry>,
+}
+
+impl Default for Modes {
+	fn default() -> Self {
+		let entries = TABLE.iter()
+			.map(|&(private, num, kind, value)| Entry { private, num, kind, value })
+			.collect();
+		Self { entries }
+	}
+}
+
+impl Modes {
+	fn entry(&self, private: bool, num: u16) -> Option<&Entry> {
+		self.entries.iter().find(|x| x.private == private && x.num == num)
+	}
+
+	pub fn get(&self, private: bool, num: u16) -> bool {
+		self.entry(private, num).map(|x| x.value).unwrap_or(false)
+	}
+
+	// return false for unknown modes
+	pub fn set(&mut self, private: bool, num: u16, value: bool) -> bool {
+		let entry = self.entries.iter_mut()
+			.find(|x| x.private == private && x.num == num);
+		match entry {
+			Some(entry) => {
+				if matches!(entry.kind, Kind::Changeable | Kind::Tracked) {
+					entry.value = value;
+				}
+				true
+			}
+			None => false,
+		}
+	}
+
+	// DECRPM value, 0 = not recognized, 1 = set, 2 = reset,
+	// 3 = permanently set, 4 = permanently reset
+	pub fn report(&self, private: bool, num: u16) -> u8 {
+		match self.entry(private, num) {
+			None => 0,
+			Some(entry) => match entry.kind {
+				Kind::Tracked => 0,
+				Kind::PermanentSet => 3,
+				Kind::PermanentReset => 4,
+				Kind::Changeable => if entry.value { 1 } else { 2 },
+			}
+		}
+	}
+}
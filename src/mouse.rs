@@ -0,0 +1,183 @@
+use std::time::SystemTime;
+
+use crate::client::WriteHalf;
+use crate::copymode::{Kind, Selection};
+use crate::keyenc::Mods;
+use crate::line::cell_char;
+use crate::mode::{self, Modes};
+
+// max interval between clicks of a double or triple click
+const MULTI_CLICK: u128 = 400;
+const DEFAULT_SEPARATORS: &str = ",│`|:\"'()[]{}<>";
+
+pub fn tracking(modes: &Modes) -> bool {
+	[mode::MOUSE_X10, mode::MOUSE_CLICK, mode::MOUSE_DRAG, mode::MOUSE_MOTION]
+		.iter()
+		.any(|x| modes.get(true, *x))
+}
+
+// report for the application, None if the event is not tracked
+pub fn encode(
+	modes: &Modes,
+	button: u8,
+	kind: u8,
+	pos: [i16; 2],
+	mods: Mods,
+) -> Option<Vec<u8>> {
+	let x10 = modes.get(true, mode::MOUSE_X10);
+	let tracked = match kind {
+		0 => true,
+		1 => !x10,
+		_ => modes.get(true, mode::MOUSE_MOTION) ||
+			(modes.get(true, mode::MOUSE_DRAG) && button != 3),
+	};
+	if !tracked || !tracking(modes) {
+		return None
+	}
+	let sgr = modes.get(true, mode::MOUSE_SGR);
+	let mut code = match button {
+		0..=3 => button as u32,
+		4 | 5 => 64 + button as u32 - 4,
+		_ => return None,
+	};
+	if kind == 1 && !sgr {
+		code = 3;
+	}
+	if kind == 2 {
+		code += 32;
+	}
+	if !x10 {
+		code += 4 * mods.shift as u32 + 8 * mods.alt as u32 + 16 * mods.ctrl as u32;
+	}
+	let [x, y] = [pos[0] as u32 + 1, pos[1] as u32 + 1];
+	if sgr {
+		let fin = if kind == 1 { 'm' } else { 'M' };
+		return Some(format!("\x1b[<{};{};{}{}", code, x, y, fin).into_bytes())
+	}
+	if x > 223 || y > 223 {
+		return None
+	}
+	Some(vec![0x1b, b'[', b'M', (32 + code) as u8, (32 + x) as u8, (32 + y) as u8])
+}
+
+pub struct MouseSelect {
+	// time and position of the last press
+	last_press: Option<(SystemTime, [usize; 2])>,
+	clicks: u8,
+	selection: Option<Selection>,
+	dragged: bool,
+	separators: Vec<char>,
+}
+
+impl Default for MouseSelect {
+	fn default() -> Self {
+		let separators = match std::env::var("VKOT_WORD_SEPARATORS") {
+			Ok(x) => x,
+			Err(_) => DEFAULT_SEPARATORS.to_string(),
+		};
+		Self {
+			last_press: None,
+			clicks: 0,
+			selection: None,
+			dragged: false,
+			separators: separators.chars().collect(),
+		}
+	}
+}
+
+impl MouseSelect {
+	fn in_word(&self, ch: char) -> bool {
+		!ch.is_whitespace() && !self.separators.contains(&ch)
+	}
+
+	// [x, absolute line] of the first and last cell of the word at pos
+	fn word(&self, wh: &WriteHalf, pos: [usize; 2]) -> ([usize; 2], [usize; 2]) {
+		let chars: Vec<char> = match wh.get_line(pos[1]) {
+			Some(line) => line.iter().map(cell_char).collect(),
+			None => return (pos, pos),
+		};
+		let x = pos[0].min(chars.len().saturating_sub(1));
+		if chars.is_empty() || !self.in_word(chars[x]) {
+			return (pos, pos)
+		}
+		let mut x0 = x;
+		while x0 > 0 && self.in_word(chars[x0 - 1]) {
+			x0 -= 1;
+		}
+		let mut x1 = x;
+		while x1 + 1 < chars.len() && self.in_word(chars[x1 + 1]) {
+			x1 += 1;
+		}
+		([x0, pos[1]], [x1, pos[1]])
+	}
+
+	fn copy(&self, wh: &mut WriteHalf) {
+		if let Some(selection) = self.selection.as_ref() {
+			let text = selection.text(wh);
+			wh.send_clipboard(&text).unwrap();
+		}
+	}
+
+	fn show(&self, wh: &mut WriteHalf) {
+		let overlays = match self.selection.as_ref() {
+			Some(selection) => selection.overlays(wh.size()[0] as usize),
+			None => Vec::new(),
+		};
+		wh.set_overlays(overlays);
+	}
+
+	// only the left button selects
+	pub fn event(&mut self, wh: &mut WriteHalf, button: u8, kind: u8, pos: [i16; 2]) {
+		let [cols, rows] = wh.size();
+		let x = pos[0].clamp(0, cols - 1) as usize;
+		let y = pos[1].clamp(0, rows - 1) as usize;
+		let pos = [x, wh.view_top() + y];
+		match (button, kind) {
+			(0, 0) => {
+				let now = SystemTime::now();
+				let multi = match self.last_press {
+					Some((time, last)) => last == pos && now.duration_since(time)
+						.map(|x| x.as_millis() < MULTI_CLICK)
+						.unwrap_or(false),
+					None => false,
+				};
+				self.clicks = if multi { self.clicks % 3 + 1 } else { 1 };
+				self.last_press = Some((now, pos));
+				self.dragged = false;
+				self.selection = Some(match self.clicks {
+					1 => Selection { kind: Kind::Char, start: pos, end: pos },
+					2 => {
+						let (start, end) = self.word(wh, pos);
+						Selection { kind: Kind::Char, start, end }
+					}
+					_ => Selection { kind: Kind::Line, start: pos, end: pos },
+				});
+				if self.clicks == 1 {
+					wh.set_overlays(Vec::new());
+				} else {
+					self.show(wh);
+				}
+			}
+			(0, 2) => {
+				let selection = match self.selection.as_mut() {
+					Some(selection) => selection,
+					None => return,
+				};
+				if selection.end == pos && !self.dragged {
+					return
+				}
+				selection.end = pos;
+				self.dragged = true;
+				self.show(wh);
+			}
+			(0, 1) => {
+				if self.clicks == 1 && !self.dragged {
+					self.selection = None;
+					return
+				}
+				self.copy(wh);
+			}
+			_ => {}
+		}
+	}
+}
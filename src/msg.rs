@@ -11,8 +11,14 @@ fn read_i16(bytes: &[u8]) -> i16 {
 
 pub enum ServerMsg {
 	Getch(u32),
-	Resized([i16; 2]),
+	// size in cells, cell size in pixels
+	Resized([i16; 2], [i16; 2]),
 	Skey([u8; 3]),
+	Focus(bool),
+	// button, kind and cell position
+	// button: 0 left, 1 middle, 2 right, 3 none, 4 wheel up, 5 wheel down
+	// kind: 0 press, 1 release, 2 motion
+	Mouse(u8, u8, [i16; 2]),
 }
 
 impl ServerMsg {
@@ -30,8 +36,10 @@ impl ServerMsg {
 				1 => {
 					let u1 = read_i16(&buf[*offset..*offset + 2]);
 					let u2 = read_i16(&buf[*offset + 2..*offset + 4]);
-					*offset += 4;
-					Self::Resized([u1, u2])
+					let u3 = read_i16(&buf[*offset + 4..*offset + 6]);
+					let u4 = read_i16(&buf[*offset + 6..*offset + 8]);
+					*offset += 8;
+					Self::Resized([u1, u2], [u3, u4])
 				}
 				2 => {
 					let d = buf[*offset];
@@ -40,6 +48,19 @@ impl ServerMsg {
 					*offset += 3;
 					Self::Skey([d, b1, b2])
 				}
+				3 => {
+					let focused = buf[*offset] != 0;
+					*offset += 1;
+					Self::Focus(focused)
+				}
+				4 => {
+					let button = buf[*offset];
+					let kind = buf[*offset + 1];
+					let x = read_i16(&buf[*offset + 2..*offset + 4]);
+					let y = read_i16(&buf[*offset + 4..*offset + 6]);
+					*offset += 6;
+					Self::Mouse(button, kind, [x, y])
+				}
 				c => return Err(anyhow!("unknown message type {:?}", c as char))
 			};
 			result.push(msg);
@@ -0,0 +1,200 @@
+use regex::Regex;
+
+use crate::client::{Mark, Overlay, WriteHalf};
+use crate::line::{cell_char, cell_width, is_blank};
+
+const MATCH_COLOR: Mark = Mark::Color(0x000000FF, 0xFFD700FF);
+const CURRENT_COLOR: Mark = Mark::Color(0x000000FF, 0xFF8C00FF);
+
+// (line, x0, x1), line is absolute and x1 exclusive
+pub type Span = (usize, usize, usize);
+
+// soft-wrapped lines joined together
+pub struct Text {
+	pub text: String,
+	// byte offset, line, column and width of each char
+	chars: Vec<(usize, usize, usize, usize)>,
+}
+
+impl Text {
+	// cells covered by the bytes start..end, one span per line
+	pub fn spans(&self, start: usize, end: usize) -> Vec<Span> {
+		let mut result: Vec<Span> = Vec::new();
+		let idx = self.chars.partition_point(|x| x.0 < start);
+		for &(byte, line, x, width) in self.chars[idx..].iter() {
+			if byte >= end {
+				break
+			}
+			match result.last_mut() {
+				Some(span) if span.0 == line => span.2 = x + width,
+				_ => result.push((line, x, x + width)),
+			}
+		}
+		result
+	}
+}
+
+// texts of lines in first..end, a text starting before first is cut
+pub fn texts(wh: &WriteHalf, first: usize, end: usize) -> Vec<Text> {
+	let mut result = Vec::new();
+	let mut text = Text { text: String::new(), chars: Vec::new() };
+	for y in first..end {
+		let line = match wh.get_line(y) {
+			Some(line) => line,
+			None => break,
+		};
+		let mut len = line.len();
+		if !line.wrapped {
+			while len > 0 && is_blank(&line[len - 1]) {
+				len -= 1;
+			}
+		}
+		let mut x = 0;
+		while x < len {
+			let width = cell_width(&line[x]);
+			let ch = cell_char(&line[x]);
+			text.chars.push((text.text.len(), y, x, width));
+			text.text.push(ch);
+			x += width;
+		}
+		if !line.wrapped {
+			let done = std::mem::replace(
+				&mut text,
+				Text { text: String::new(), chars: Vec::new() },
+			);
+			result.push(done);
+		}
+	}
+	if !text.chars.is_empty() {
+		result.push(text);
+	}
+	result
+}
+
+pub struct Search {
+	query: String,
+	editing: bool,
+	// sorted from top to bottom
+	matches: Vec<Vec<Span>>,
+	current: usize,
+	error: Option<String>,
+}
+
+impl Search {
+	pub fn new(wh: &mut WriteHalf) -> Self {
+		let result = Self {
+			query: String::new(),
+			editing: true,
+			matches: Vec::new(),
+			current: 0,
+			error: None,
+		};
+		result.update(wh);
+		result
+	}
+
+	fn run(&mut self, wh: &WriteHalf) {
+		self.matches.clear();
+		self.error = None;
+		let regex = match Regex::new(&self.query) {
+			Ok(regex) => regex,
+			Err(e) => {
+				let e = e.to_string();
+				self.error = Some(e.lines().last().unwrap_or("").to_string());
+				return
+			}
+		};
+		for text in texts(wh, wh.first_line(), wh.end_line()).into_iter() {
+			for m in regex.find_iter(&text.text) {
+				if m.start() == m.end() {
+					continue
+				}
+				self.matches.push(text.spans(m.start(), m.end()));
+			}
+		}
+		// start from the bottom, like searching backwards
+		self.current = self.matches.len().saturating_sub(1);
+	}
+
+	fn update(&self, wh: &mut WriteHalf) {
+		let mut overlays = Vec::new();
+		for (idx, spans) in self.matches.iter().enumerate() {
+			let mark = if idx == self.current { CURRENT_COLOR } else { MATCH_COLOR };
+			for &(line, x0, x1) in spans.iter() {
+				overlays.push(Overlay { line, x0, x1, mark });
+			}
+		}
+		wh.set_overlays(overlays);
+		let status = if self.editing {
+			format!("/{}", self.query)
+		} else if let Some(e) = self.error.as_ref() {
+			format!("bad regex: {}", e)
+		} else if self.matches.is_empty() {
+			format!("no match: {}", self.query)
+		} else {
+			format!("[{}/{}] {}", self.current + 1, self.matches.len(), self.query)
+		};
+		wh.set_status(Some(&status));
+		if !self.editing {
+			if let Some(spans) = self.matches.get(self.current) {
+				wh.show_line(spans[0].0);
+			}
+		}
+	}
+
+	// up = towards older lines
+	fn move_current(&mut self, up: bool) {
+		let len = self.matches.len();
+		if len == 0 || self.editing {
+			return
+		}
+		self.current = if up {
+			(self.current + len - 1) % len
+		} else {
+			(self.current + 1) % len
+		};
+	}
+
+	pub fn step(&mut self, wh: &mut WriteHalf, up: bool) {
+		self.move_current(up);
+		self.update(wh);
+	}
+
+	// return false if the search is closed
+	pub fn key(&mut self, wh: &mut WriteHalf, ch: u32) -> bool {
+		let ch = match char::from_u32(ch) {
+			Some(ch) => ch,
+			None => return true,
+		};
+		if self.editing {
+			match ch {
+				'\x1b' => return self.close(wh),
+				'\r' | '\n' => {
+					self.editing = false;
+					self.run(wh);
+				}
+				'\x7f' | '\x08' => {
+					self.query.pop();
+				}
+				ch if !ch.is_control() => self.query.push(ch),
+				_ => {}
+			}
+		} else {
+			match ch {
+				'n' => self.move_current(true),
+				'N' => self.move_current(false),
+				'/' => self.editing = true,
+				'\x1b' | 'q' | '\r' => return self.close(wh),
+				_ => {}
+			}
+		}
+		self.update(wh);
+		true
+	}
+
+	fn close(&self, wh: &mut WriteHalf) -> bool {
+		wh.set_overlays(Vec::new());
+		wh.set_status(None);
+		false
+	}
+}
@@ -0,0 +1,251 @@
+use crate::image::Image;
+
+// keep broken streams from allocating forever
+const MAX_SIZE: usize = 4096;
+
+// VT340 default palette
+const DEFAULT_PALETTE: [u32; 16] = [
+	0x000000FF, 0x3333CCFF, 0xCC2121FF, 0x33CC33FF,
+	0xCC33CCFF, 0x33CCCCFF, 0xCCCC33FF, 0x878787FF,
+	0x424242FF, 0x545499FF, 0x994242FF, 0x549954FF,
+	0x995499FF, 0x549999FF, 0x999954FF, 0xCCCCCCFF,
+];
+
+#[derive(PartialEq)]
+enum State {
+	Data,
+	Raster,
+	Color,
+	Repeat,
+}
+
+pub struct SixelDecoder {
+	state: State,
+	params: Vec<u32>,
+	palette: Vec<u32>,
+	color: u32,
+	repeat: usize,
+	x: usize,
+	y: usize,
+	// used size, cropped to in finish
+	width: usize,
+	height: usize,
+	// allocated size, grown geometrically
+	stride: usize,
+	rows: usize,
+	// color value, 0 = transparent
+	pixels: Vec<u32>,
+	background: u32,
+}
+
+fn hls2rgb(h: u32, l: u32, s: u32) -> u32 {
+	// sixel hue starts from blue
+	let h = ((h + 240) % 360) as f32 / 60.0;
+	let l = l.min(100) as f32 / 100.0;
+	let s = s.min(100) as f32 / 100.0;
+	let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+	let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+	let m = l - c / 2.0;
+	let (r, g, b) = match h as u32 {
+		0 => (c, x, 0.0),
+		1 => (x, c, 0.0),
+		2 => (0.0, c, x),
+		3 => (0.0, x, c),
+		4 => (x, 0.0, c),
+		_ => (c, 0.0, x),
+	};
+	let conv = |v: f32| ((v + m) * 255.0).round() as u32;
+	(conv(r) << 24) + (conv(g) << 16) + (conv(b) << 8) + 255
+}
+
+fn rgb_percent(r: u32, g: u32, b: u32) -> u32 {
+	let conv = |v: u32| v.min(100) * 255 / 100;
+	(conv(r) << 24) + (conv(g) << 16) + (conv(b) << 8) + 255
+}
+
+impl SixelDecoder {
+	// p2 = 1: unset pixels stay transparent
+	pub fn new(p2: u16) -> Self {
+		let mut palette = vec![0x000000FF; 256];
+		palette[..16].copy_from_slice(&DEFAULT_PALETTE);
+		let background = if p2 == 1 { 0 } else { palette[0] };
+		Self {
+			state: State::Data,
+			params: Vec::new(),
+			palette,
+			color: DEFAULT_PALETTE[0],
+			repeat: 1,
+			x: 0,
+			y: 0,
+			width: 0,
+			height: 0,
+			stride: 0,
+			rows: 0,
+			pixels: Vec::new(),
+			background,
+		}
+	}
+
+	fn param(&self, idx: usize) -> u32 {
+		self.params.get(idx).cloned().unwrap_or(0)
+	}
+
+	fn grow(&mut self, width: usize, height: usize) {
+		self.width = width.max(self.width).min(MAX_SIZE);
+		self.height = height.max(self.height).min(MAX_SIZE);
+		if self.width <= self.stride && self.height <= self.rows {
+			return
+		}
+		let stride = self.width.max(self.stride * 2).min(MAX_SIZE);
+		let rows = self.height.max(self.rows * 2).min(MAX_SIZE);
+		let mut pixels = vec![self.background; stride * rows];
+		for y in 0..self.rows {
+			let src = &self.pixels[y * self.stride..(y + 1) * self.stride];
+			pixels[y * stride..y * stride + self.stride].copy_from_slice(src);
+		}
+		self.pixels = pixels;
+		self.stride = stride;
+		self.rows = rows;
+	}
+
+	fn draw(&mut self, bits: u8) {
+		let count = std::mem::replace(&mut self.repeat, 1);
+		self.grow(self.x + count, self.y + 6);
+		for dy in 0..6 {
+			if bits & (1 << dy) == 0 {
+				continue
+			}
+			let y = self.y + dy;
+			if y >= self.height {
+				break
+			}
+			for x in self.x..(self.x + count).min(self.width) {
+				self.pixels[y * self.stride + x] = self.color;
+			}
+		}
+		self.x += count;
+	}
+
+	fn finish_command(&mut self) {
+		match self.state {
+			State::Raster => {
+				// Pan;Pad;Ph;Pv, only size is used
+				let w = self.param(2) as usize;
+				let h = self.param(3) as usize;
+				self.grow(w, h);
+			}
+			State::Color => {
+				let idx = self.param(0) as usize % self.palette.len();
+				if self.params.len() >= 5 {
+					let [a, b, c] = [self.param(2), self.param(3), self.param(4)];
+					self.palette[idx] = match self.param(1) {
+						1 => hls2rgb(a, b, c),
+						2 => rgb_percent(a, b, c),
+						_ => self.palette[idx],
+					};
+				}
+				self.color = self.palette[idx];
+			}
+			State::Repeat => {
+				self.repeat = (self.param(0) as usize).max(1);
+			}
+			State::Data => {}
+		}
+		self.state = State::Data;
+		self.params.clear();
+	}
+
+	pub fn put(&mut self, byte: u8) {
+		if self.state != State::Data {
+			match byte {
+				b'0'..=b'9' => {
+					if self.params.is_empty() {
+						self.params.push(0);
+					}
+					let last = self.params.last_mut().unwrap();
+					*last = last.saturating_mul(10).saturating_add((byte - b'0') as u32);
+					return
+				}
+				b';' => {
+					if self.params.is_empty() {
+						self.params.push(0);
+					}
+					// at most 5 are used, anything after lands in an ignored 6th
+					if self.params.len() < 6 {
+						self.params.push(0);
+					}
+					return
+				}
+				_ => self.finish_command(),
+			}
+		}
+		match byte {
+			b'"' => self.state = State::Raster,
+			b'#' => self.state = State::Color,
+			b'!' => self.state = State::Repeat,
+			b'$' => self.x = 0,
+			b'-' => {
+				self.x = 0;
+				self.y += 6;
+			}
+			0x3f..=0x7e => self.draw(byte - 0x3f),
+			_ => {}
+		}
+	}
+
+	pub fn finish(mut self) -> Option<Image> {
+		self.finish_command();
+		if self.width == 0 || self.height == 0 {
+			return None
+		}
+		let mut data = Vec::with_capacity(self.width * self.height * 4);
+		for row in self.pixels.chunks(self.stride).take(self.height) {
+			for pixel in row[..self.width].iter() {
+				data.extend(pixel.to_be_bytes());
+			}
+		}
+		Some(Image {
+			width: self.width as u32,
+			height: self.height as u32,
+			data,
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn decode(data: &[u8]) -> Option<Image> {
+		let mut decoder = SixelDecoder::new(0);
+		for byte in data.iter() {
+			decoder.put(*byte);
+		}
+		decoder.finish()
+	}
+
+	#[test]
+	fn crop_to_used_size() {
+		let image = decode(b"#1~~~-#2!5~").unwrap();
+		assert_eq!([image.width, image.height], [5, 12]);
+		assert_eq!(image.data.len(), 5 * 12 * 4);
+		let pixel = |x: usize, y: usize| {
+			let idx = (y * 5 + x) * 4;
+			u32::from_be_bytes(image.data[idx..idx + 4].try_into().unwrap())
+		};
+		assert_eq!(pixel(2, 0), DEFAULT_PALETTE[1]);
+		assert_eq!(pixel(3, 0), DEFAULT_PALETTE[0]);
+		assert_eq!(pixel(4, 11), DEFAULT_PALETTE[2]);
+	}
+
+	#[test]
+	fn wide_image_is_linear() {
+		// one column at a time, over a tall raster
+		let mut data = b"\"1;1;1;600".to_vec();
+		data.extend(std::iter::repeat_n(b'~', MAX_SIZE + 100));
+		let start = std::time::Instant::now();
+		let image = decode(&data).unwrap();
+		assert_eq!([image.width, image.height], [MAX_SIZE as u32, 600]);
+		assert!(start.elapsed() < std::time::Duration::from_secs(2));
+	}
+}
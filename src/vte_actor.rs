@@ -1,9 +1,99 @@
 use crate::color_table::ColorTable;
-use crate::client::WriteHalf;
+use crate::client::{
+	WriteHalf,
+	DE_PROTECTED,
+	DE_UNDERLINE,
+	DE_ULCOLOR,
+	DE_ULCOLOR_SET,
+	DE_ULCOLOR_SHIFT,
+	DE_ULSTYLE,
+	DE_ULSTYLE_SHIFT,
+};
+use crate::graphics::{self, KittyGraphics};
+use crate::keyenc::{
+	KITTY_ALL_KEYS,
+	KITTY_DISAMBIGUATE,
+	KITTY_EVENT_TYPES,
+	KITTY_TEXT,
+};
+use crate::mode::{self, Modes};
+use crate::sixel::SixelDecoder;
+
+enum Dcs {
+	Sixel(SixelDecoder),
+	// XTGETTCAP
+	Tcap(Vec<u8>),
+	// DECRQSS
+	Rqss(Vec<u8>),
+}
+
+const KITTY_SUPPORTED: u8 = KITTY_DISAMBIGUATE | KITTY_EVENT_TYPES | KITTY_ALL_KEYS | KITTY_TEXT;
+
+// terminfo capabilities reported by XTGETTCAP
+const TCAP: &[(&str, &str)] = &[
+	("TN", "vkot"),
+	("name", "vkot"),
+	("Co", "256"),
+	("colors", "256"),
+	("RGB", "8/8/8"),
+	("Tc", ""),
+	("setrgbf", "\x1b[38;2;%p1%d;%p2%d;%p3%dm"),
+	("setrgbb", "\x1b[48;2;%p1%d;%p2%d;%p3%dm"),
+	("Smulx", "\x1b[4:%p1%dm"),
+	// no Setulc, it takes rgb but underline color is only a palette index
+];
+
+trait CsiVec {
+	fn gv(&self, idx: usize) -> u16;
+	fn gv0(&self, idx: usize) -> u16;
+}
+
+impl CsiVec for [u16] {
+	fn gv(&self, idx: usize) -> u16 {
+		self.get(idx).cloned().unwrap_or(0).max(1)
+	}
+	fn gv0(&self, idx: usize) -> u16 {
+		self.get(idx).cloned().unwrap_or(0)
+	}
+}
+
+// rectangle coordinates beyond i16 are off screen anyway
+fn rect_coord(x: u16) -> i16 {
+	i16::try_from(x).unwrap_or(i16::MAX)
+}
+
+fn pack_rgb(r: u16, g: u16, b: u16) -> u32 {
+	let [r, g, b] = [r, g, b].map(|x| x.min(255) as u32);
+	(r << 24) + (g << 16) + (b << 8) + 255
+}
+
+fn hex_encode(data: &[u8]) -> String {
+	data.iter().map(|x| format!("{:02X}", x)).collect()
+}
+
+fn hex_decode(data: &[u8]) -> Option<Vec<u8>> {
+	if !data.len().is_multiple_of(2) {
+		return None
+	}
+	data.chunks(2)
+		.map(|x| std::str::from_utf8(x).ok().and_then(|x| u8::from_str_radix(x, 16).ok()))
+		.collect()
+}
 
 pub struct VteActor {
 	pub wh: WriteHalf,
 	color_table: ColorTable,
+	pub modes: Modes,
+	// DECSCUSR, not rendered
+	cursor_style: u16,
+	// ?2026, start time of the synchronized update
+	pub sync_since: Option<std::time::SystemTime>,
+	// kitty keyboard protocol flags, main and alternate screen
+	kitty_stack: [Vec<u8>; 2],
+	// bytes to be written back to the pty
+	pub reply: Vec<u8>,
+	dcs: Option<Dcs>,
+	kitty_graphics: KittyGraphics,
 }
 
 impl VteActor {
@@ -11,37 +101,307 @@ impl VteActor {
 		Self {
 			wh,
 			color_table: Default::default(),
+			modes: Default::default(),
+			cursor_style: 0,
+			sync_since: None,
+			kitty_stack: Default::default(),
+			reply: Vec::new(),
+			dcs: None,
+			kitty_graphics: Default::default(),
+		}
+	}
+
+	pub fn alt_screen(&self) -> bool {
+		[47, 1047, 1049].iter().any(|x| self.modes.get(true, *x))
+	}
+
+	pub fn end_sync(&mut self) {
+		self.sync_since = None;
+		self.modes.set(true, mode::SYNC, false);
+	}
+
+	// side effects of SM/RM
+	fn mode_changed(&mut self, private: bool, num: u16, value: bool) {
+		if !private {
+			return
+		}
+		match num {
+			mode::DECLRMM if !value => self.wh.reset_margins(),
+			mode::SYNC => {
+				self.sync_since = if value {
+					Some(std::time::SystemTime::now())
+				} else {
+					None
+				};
+			}
+			47 | 1047 | 1049 if !self.alt_screen() => self.kitty_stack[1].clear(),
+			_ => {}
+		}
+	}
+
+	pub fn kitty_flags(&self) -> u8 {
+		let stack = &self.kitty_stack[self.alt_screen() as usize];
+		stack.last().cloned().unwrap_or(0)
+	}
+
+	fn kitty_keyboard(&mut self, simple: Vec<u16>, interm: &[u8]) {
+		let current = self.kitty_flags();
+		let stack = &mut self.kitty_stack[self.alt_screen() as usize];
+		// alternate keys (4) are not implemented
+		let flags = simple.first().cloned().unwrap_or(0) as u8 & KITTY_SUPPORTED;
+		match interm {
+			b">" => {
+				if stack.len() >= 16 {
+					stack.remove(0);
+				}
+				stack.push(flags);
+			}
+			b"<" => {
+				let count = simple.first().cloned().unwrap_or(1).max(1) as usize;
+				let len = stack.len().saturating_sub(count);
+				stack.truncate(len);
+			}
+			b"=" => {
+				let new = match simple.get(1).cloned().unwrap_or(1) {
+					1 => flags,
+					2 => current | flags,
+					3 => current & !flags,
+					_ => return,
+				};
+				match stack.last_mut() {
+					Some(last) => *last = new,
+					None => stack.push(new),
+				}
+			}
+			b"?" => {
+				self.reply.extend(format!("\x1b[?{}u", current).bytes());
+			}
+			_ => eprintln!("uh csi u: {:?}", simple),
+		}
+	}
+
+	fn xtgettcap(&mut self, data: &[u8]) {
+		for name in data.split(|x| *x == b';') {
+			let value = hex_decode(name)
+				.and_then(|x| String::from_utf8(x).ok())
+				.and_then(|x| TCAP.iter().find(|cap| cap.0 == x));
+			let name = String::from_utf8_lossy(name);
+			let response = match value {
+				Some((_, "")) => format!("\x1bP1+r{}\x1b\\", name),
+				Some((_, value)) => {
+					format!("\x1bP1+r{}={}\x1b\\", name, hex_encode(value.as_bytes()))
+				}
+				None => format!("\x1bP0+r{}\x1b\\", name),
+			};
+			self.reply.extend(response.bytes());
+		}
+	}
+
+	fn sgr_report(&self) -> String {
+		let (pen, reversed) = self.wh.pen();
+		let mut result = "0".to_string();
+		let style = (pen.de & DE_ULSTYLE) >> DE_ULSTYLE_SHIFT;
+		if style != 0 {
+			result += &format!(";4:{}", style);
+		}
+		if pen.de & DE_ULCOLOR_SET != 0 {
+			result += &format!(";58:5:{}", (pen.de & DE_ULCOLOR) >> DE_ULCOLOR_SHIFT);
+		}
+		if reversed {
+			result += ";7";
+		}
+		let rgb = |c: u32| format!("{};{};{}", c >> 24, (c >> 16) & 0xFF, (c >> 8) & 0xFF);
+		if pen.fg != u32::MAX {
+			result += &format!(";38;2;{}", rgb(pen.fg));
+		}
+		if pen.bg != 0 {
+			result += &format!(";48;2;{}", rgb(pen.bg));
+		}
+		result
+	}
+
+	fn decrqss(&mut self, data: &[u8]) {
+		let value = match data {
+			b"m" => format!("{}m", self.sgr_report()),
+			b"r" => {
+				let [top, bot] = self.wh.scroll_region();
+				format!("{};{}r", top + 1, bot)
+			}
+			b" q" => format!("{} q", self.cursor_style),
+			b"\"q" => {
+				let protected = self.wh.pen().0.de & DE_PROTECTED != 0;
+				format!("{}\"q", protected as u8)
+			}
+			_ => {
+				self.reply.extend(b"\x1bP0$r\x1b\\");
+				return
+			}
+		};
+		self.reply.extend(format!("\x1bP1$r{}\x1b\\", value).bytes());
+	}
+
+	// Pt;Pl;Pb;Pr starting from idx, as [x0, y0, x1, y1)
+	fn rect_param(&self, simple: &[u16], idx: usize) -> [i16; 4] {
+		let [cols, rows] = self.wh.size();
+		let top = rect_coord(simple.gv(idx)) - 1;
+		let left = rect_coord(simple.gv(idx + 1)) - 1;
+		let bot = match simple.gv0(idx + 2) {
+			0 => rows,
+			x => rect_coord(x),
+		};
+		let right = match simple.gv0(idx + 3) {
+			0 => cols,
+			x => rect_coord(x),
+		};
+		[left, top, right, bot]
+	}
+
+	// DECFRA, DECERA, DECCRA, DECCARA, DECRARA
+	fn rect_op(&mut self, simple: Vec<u16>, action: char) {
+		match action {
+			'x' => {
+				let ch = simple.gv0(0) as u32;
+				if !(32..127).contains(&ch) && !(160..256).contains(&ch) {
+					return
+				}
+				let rect = self.rect_param(&simple, 1);
+				self.wh.fill_rect(rect, Some(ch));
+			}
+			'z' => {
+				let rect = self.rect_param(&simple, 0);
+				self.wh.fill_rect(rect, None);
+			}
+			'v' => {
+				let rect = self.rect_param(&simple, 0);
+				// source and destination pages are ignored
+				let dst = [rect_coord(simple.gv(6)) - 1, rect_coord(simple.gv(5)) - 1];
+				self.wh.copy_rect(rect, dst);
+			}
+			'r' | 't' => {
+				let rect = self.rect_param(&simple, 0);
+				let reverse = action == 't';
+				let attrs = if simple.len() > 4 { &simple[4..] } else { &[0][..] };
+				for attr in attrs.iter().cloned() {
+					match (attr, reverse) {
+						(0, false) => {
+							self.wh.reverse_rect(rect, 0);
+							self.wh.change_rect_decoration(rect, !DE_PROTECTED, 0);
+						}
+						(0, true) => {
+							self.wh.change_rect_decoration(rect, DE_UNDERLINE, 2);
+							self.wh.reverse_rect(rect, 2);
+						}
+						// bold and blink are not drawn, as with SGR
+						(1 | 5, _) | (22 | 25, false) => {}
+						(4, _) => {
+							let ty = if reverse { 2 } else { 1 };
+							self.wh.change_rect_decoration(rect, DE_UNDERLINE, ty);
+						}
+						(24, false) => {
+							self.wh.change_rect_decoration(rect, DE_UNDERLINE | DE_ULSTYLE, 0);
+						}
+						(7, false) => self.wh.reverse_rect(rect, 1),
+						(27, false) => self.wh.reverse_rect(rect, 0),
+						(7, true) => self.wh.reverse_rect(rect, 2),
+						_ => eprintln!("uh rect attr {} {}", action, attr),
+					}
+				}
+			}
+			_ => unreachable!(),
+		}
+	}
+
+	pub fn apc_dispatch(&mut self, data: Vec<u8>) {
+		match data.first() {
+			Some(b'G') => {
+				let reply = self.kitty_graphics.command(&data[1..], &mut self.wh);
+				self.reply.extend(reply);
+			}
+			_ => eprintln!("uh apc {:?}", String::from_utf8_lossy(&data)),
+		}
+	}
+
+	// OSC 1337, data is after "1337;"
+	pub fn iterm_dispatch(&mut self, data: Vec<u8>) {
+		graphics::iterm_file(&[&data], &mut self.wh);
+	}
+
+	// 5;n or 2;r;g;b after 38, 48 and 58, as subparams or params
+	fn extended_args(
+		group: &[u16],
+		iter: &mut std::vec::IntoIter<Vec<u16>>,
+	) -> Option<Vec<u16>> {
+		let args = if group.len() > 1 {
+			let mut args = group[1..].to_vec();
+			// 2:colorspace:r:g:b
+			if args[0] == 2 && args.len() >= 5 {
+				args.remove(1);
+			}
+			args
+		} else {
+			let ty = iter.next()?[0];
+			let count = match ty {
+				2 => 3,
+				5 => 1,
+				_ => 0,
+			};
+			let mut args = vec![ty];
+			for _ in 0..count {
+				args.push(iter.next()?[0]);
+			}
+			args
+		};
+		Some(args)
+	}
+
+	fn extended_color(
+		&self,
+		group: &[u16],
+		iter: &mut std::vec::IntoIter<Vec<u16>>,
+	) -> Option<u32> {
+		match Self::extended_args(group, iter)?[..] {
+			[5, idx, ..] => Some(self.color_table.rgb_from_256color(idx as u8)),
+			[2, r, g, b, ..] => Some(pack_rgb(r, g, b)),
+			_ => None,
 		}
 	}
 
+	// params are grouped with their subparams
 	pub fn set_sgr(
 		&mut self,
-		simple: Vec<u16>,
+		groups: Vec<Vec<u16>>,
 	) -> std::io::Result<()> {
-		let mut iter = simple.into_iter();
+		let mut iter = groups.into_iter();
 		loop {
-			let arg = match iter.next() {
+			let group = match iter.next() {
 				Some(x) => x,
 				None => return Ok(()),
 			};
+			let arg = group[0];
 			match arg {
 				0 => {
 					self.wh.fg_color(u32::MAX);
 					self.wh.bg_color(0);
 					self.wh.reverse_color(false);
-					self.wh.set_decoration(u32::MAX, 0);
+					// DECSCA is not an SGR attribute
+					self.wh.set_decoration(!DE_PROTECTED, 0);
 				}
 				1 => {
 					// bold
 				}
 				4 => {
-					self.wh.set_decoration(1 << 2, 1);
+					// 4:0 = none, 4:1 - 4:5 = styles
+					let style = group.get(1).cloned().unwrap_or(1);
+					self.wh.set_underline(style);
 				}
 				7 => {
 					self.wh.reverse_color(true);
 				}
+				21 => {
+					self.wh.set_underline(2);
+				}
 				24 => {
-					self.wh.set_decoration(1 << 2, 0);
+					self.wh.set_underline(0);
 				}
 				27 => {
 					self.wh.reverse_color(false);
@@ -53,15 +413,9 @@ impl VteActor {
 					);
 				}
 				38 => {
-					let nx = iter.next().unwrap();
-					if nx == 5 {
-						let nx = iter.next().unwrap();
-						self.wh.fg_color(self
-							.color_table
-							.rgb_from_256color(nx as u8)
-						);
-					} else {
-						eprintln!("uh color");
+					match self.extended_color(&group, &mut iter) {
+						Some(color) => self.wh.fg_color(color),
+						None => eprintln!("uh color"),
 					}
 				}
 				39 => {
@@ -74,20 +428,29 @@ impl VteActor {
 					);
 				}
 				48 => {
-					let nx = iter.next().unwrap();
-					if nx == 5 {
-						let nx = iter.next().unwrap();
-						self.wh.bg_color(self
-							.color_table
-							.rgb_from_256color(nx as u8)
-						);
-					} else {
-						eprintln!("uh color");
+					match self.extended_color(&group, &mut iter) {
+						Some(color) => self.wh.bg_color(color),
+						None => eprintln!("uh color"),
 					}
 				}
 				49 => {
 					self.wh.bg_color(0);
 				}
+				58 => {
+					// only palette colors fit in the cell, rgb is approximated
+					let idx = match Self::extended_args(&group, &mut iter).as_deref() {
+						Some(&[5, idx, ..]) => Some(idx as u8),
+						Some(&[2, r, g, b, ..]) => Some(self.color_table.nearest(pack_rgb(r, g, b))),
+						_ => None,
+					};
+					match idx {
+						Some(idx) => self.wh.underline_color(Some(idx)),
+						None => eprintln!("uh color"),
+					}
+				}
+				59 => {
+					self.wh.underline_color(None);
+				}
 				90..=97 => {
 					self.wh.fg_color(self
 						.color_table
@@ -114,21 +477,9 @@ impl VteActor {
 		interm: &[u8],
 		action: char,
 	) -> std::io::Result<()> {
-		trait CsiVec {
-			fn gv(&self, idx: usize) -> u16;
-			fn gv0(&self, idx: usize) -> u16;
-		}
-		impl CsiVec for Vec<u16> {
-			fn gv(&self, idx: usize) -> u16 {
-				self.get(idx).cloned().unwrap_or(0).max(1)
-			}
-			fn gv0(&self, idx: usize) -> u16 {
-				self.get(idx).cloned().unwrap_or(0)
-			}
-		}
 		match action {
-			'm' => {
-				self.set_sgr(simple)?;
+			'x' | 'z' | 'v' | 'r' | 't' if interm == b"$" => {
+				self.rect_op(simple, action);
 			}
 			'A' => {
 				self.wh.loc(3, -(simple.gv(0) as i16));
@@ -144,11 +495,11 @@ impl VteActor {
 			}
 			'J' => {
 				let ty = simple.gv0(0);
-				self.wh.erase_display(ty);
+				self.wh.erase_display(ty, interm == b"?");
 			}
 			'K' => {
 				let ty = simple.gv0(0);
-				self.wh.erase_line(ty);
+				self.wh.erase_line(ty, interm == b"?");
 			}
 			'H' | 'f' => {
 				// coord start from 1
@@ -163,19 +514,19 @@ impl VteActor {
 				self.wh.loc(1, px as i16 - 1);
 			}
 			'h' | 'l' => {
-				if simple.is_empty() {
-					return Ok(())
-				}
-				match simple[0] {
-					2004 => {}, // backet copy/paste
-					1000 | 1002 | 1003 | 1006 => {}, // mouse related
-					1 => {}, // application mode
-					_ => eprintln!(
-						"uh csi {}: {:?} {}",
-						action,
-						simple,
-						String::from_utf8_lossy(interm),
-					)
+				let private = interm == b"?";
+				let value = action == 'h';
+				for num in simple.iter().cloned() {
+					if self.modes.set(private, num, value) {
+						self.mode_changed(private, num, value);
+					} else {
+						eprintln!(
+							"uh mode {}: {} {}",
+							action,
+							num,
+							String::from_utf8_lossy(interm),
+						)
+					}
 				}
 			}
 			'r' => {
@@ -188,6 +539,63 @@ impl VteActor {
 					eprintln!("uh csi r");
 				}
 			}
+			's' if interm.is_empty() => {
+				if self.modes.get(true, mode::DECLRMM) {
+					let right = match simple.gv0(1) {
+						0 => self.wh.size()[0] as u16,
+						x => x,
+					};
+					self.wh.set_margins(simple.gv(0), right);
+				} else {
+					self.wh.save_cursor();
+				}
+			}
+			'u' if interm.is_empty() => {
+				self.wh.restore_cursor();
+			}
+			'u' => {
+				self.kitty_keyboard(simple, interm);
+			}
+			'@' => {
+				self.wh.ich(simple.gv(0) as i16);
+			}
+			'P' => {
+				self.wh.dch(simple.gv(0) as i16);
+			}
+			't' if interm.is_empty() => {
+				// XTWINOPS reports
+				let [cols, rows] = self.wh.size();
+				let [cw, ch] = self.wh.images.cell_px;
+				let report = match simple.gv0(0) {
+					14 => {
+						let h = rows as i32 * ch as i32;
+						format!("4;{};{}", h, cols as i32 * cw as i32)
+					}
+					16 => format!("6;{};{}", ch, cw),
+					18 => format!("8;{};{}", rows, cols),
+					19 => format!("9;{};{}", rows, cols),
+					_ => {
+						eprintln!("uh csi t: {:?}", simple);
+						return Ok(())
+					}
+				};
+				self.reply.extend(format!("\x1b[{}t", report).bytes());
+			}
+			'q' if interm == b"\"" => {
+				// DECSCA
+				self.wh.set_protected(simple.gv0(0) == 1);
+			}
+			'q' if interm == b" " => {
+				self.cursor_style = simple.gv0(0);
+			}
+			'p' if interm == b"?$" || interm == b"$" => {
+				// DECRQM
+				let private = interm[0] == b'?';
+				let num = simple.gv0(0);
+				let value = self.modes.report(private, num);
+				let prefix = if private { "?" } else { "" };
+				self.reply.extend(format!("\x1b[{}{};{}$y", prefix, num, value).bytes());
+			}
 			'X' => {
 				let count = simple.gv(0);
 				self.wh.ech(count as i16);
@@ -220,7 +628,7 @@ impl vte::Perform for VteActor {
 				self.wh.loc(2, -1);
 			}
 			b'\x0d' => {
-				self.wh.loc(0, 0);
+				self.wh.carriage_return();
 			}
 			b'\x09' => {
 				self.wh.tab();
@@ -240,12 +648,62 @@ impl vte::Perform for VteActor {
 		_ignore: bool,
 		action: char,
 	) {
+		if action == 'm' && interm.is_empty() {
+			let groups = params.iter().map(|x| x.to_vec()).collect();
+			self.set_sgr(groups).unwrap();
+			return
+		}
 		let simple = params.iter().map(|x| x[0]).collect::<Vec<u16>>();
 		self.csi_easy(simple, interm, action).unwrap();
 	}
 
+	fn hook(
+		&mut self,
+		params: &vte::Params,
+		interm: &[u8],
+		_ignore: bool,
+		action: char,
+	) {
+		let simple = params.iter().map(|x| x[0]).collect::<Vec<u16>>();
+		self.dcs = match (interm, action) {
+			(b"", 'q') => {
+				let p2 = simple.get(1).cloned().unwrap_or(0);
+				Some(Dcs::Sixel(SixelDecoder::new(p2)))
+			}
+			(b"+", 'q') => Some(Dcs::Tcap(Vec::new())),
+			(b"$", 'q') => Some(Dcs::Rqss(Vec::new())),
+			_ => {
+				eprintln!("uh dcs {}: {:?} {}", action, simple, String::from_utf8_lossy(interm));
+				None
+			}
+		};
+	}
+
+	fn put(&mut self, byte: u8) {
+		match self.dcs.as_mut() {
+			Some(Dcs::Sixel(decoder)) => decoder.put(byte),
+			Some(Dcs::Tcap(buf)) | Some(Dcs::Rqss(buf)) if buf.len() < 4096 => buf.push(byte),
+			_ => {}
+		}
+	}
+
+	fn unhook(&mut self) {
+		match self.dcs.take() {
+			Some(Dcs::Sixel(decoder)) => {
+				if let Some(image) = decoder.finish() {
+					self.wh.place_image(image);
+				}
+			}
+			Some(Dcs::Tcap(buf)) => self.xtgettcap(&buf),
+			Some(Dcs::Rqss(buf)) => self.decrqss(&buf),
+			None => {}
+		}
+	}
+
 	fn esc_dispatch(&mut self, interm: &[u8], _ignore: bool, byte: u8) {
 		match byte {
+			b'7' if interm.is_empty() => self.wh.save_cursor(),
+			b'8' if interm.is_empty() => self.wh.restore_cursor(),
 			b'B' => {} // BPH
 			b'M' => self.wh.scroll(false),
 			b'=' => {} // ignore keypad
@@ -254,3 +712,34 @@ impl vte::Perform for VteActor {
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::os::unix::net::UnixStream;
+
+	#[test]
+	fn rect_ops_max_params() {
+		let (stream, _peer) = UnixStream::pair().unwrap();
+		let mut actor = VteActor::new(WriteHalf::new(stream));
+		let mut parser = vte::Parser::new();
+		let seqs = [
+			"\x1b[65;32768;1;4;10$x",
+			"\x1b[65;1;32768;4;10$x",
+			"\x1b[65;65535;65535;65535;65535$x",
+			"\x1b[65535;65535;65535;65535$z",
+			"\x1b[1;1;4;10;1;1;32767$v",
+			"\x1b[1;1;4;10;1;65535;65535$v",
+			"\x1b[65535;65535;65535;65535;65535;65535;65535$v",
+			"\x1b[65535;65535;65535;65535;7$r",
+			"\x1b[1;1;65535;65535;7$t",
+			"\x1b[65535;65535;65535;65535;4$t",
+		];
+		for seq in seqs {
+			for byte in seq.bytes() {
+				parser.advance(&mut actor, byte);
+			}
+		}
+		assert_eq!(actor.wh.size(), [80, 24]);
+	}
+}